@@ -0,0 +1,136 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// First line of every archive, followed by the format version.
+const MAGIC: &str = "TROVE-BACKUP";
+
+/// Version of the archives this build writes.
+pub const VERSION: u32 = 1;
+
+/// A snapshot of every file in a data directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Archive {
+	pub version: u32,
+	/// Paths relative to the data directory with their contents.
+	pub files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+/// Reads every file under a data directory.
+pub fn snapshot(data_dir: &Path) -> io::Result<Archive> {
+	let mut files = Vec::new();
+	add_files(data_dir, Path::new(""), &mut files)?;
+	files.sort_by(|(a, _), (b, _)| a.cmp(b));
+	Ok(Archive { version: VERSION, files })
+}
+
+fn add_files(dir: &Path, relative: &Path, files: &mut Vec<(PathBuf, Vec<u8>)>) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let path = relative.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			add_files(&entry.path(), &path, files)?;
+		} else {
+			files.push((path, fs::read(entry.path())?));
+		}
+	}
+	Ok(())
+}
+
+/// Writes the files of an archive into an empty or missing directory.
+pub fn unpack(archive: &Archive, data_dir: &Path) -> io::Result<()> {
+	for (path, bytes) in &archive.files {
+		let path = data_dir.join(path);
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		fs::write(path, bytes)?;
+	}
+	Ok(())
+}
+
+/// Lays out an archive as a header line and then, for each file, a line
+/// with its path and length followed by its bytes.
+pub fn encode(archive: &Archive) -> Vec<u8> {
+	let mut bytes = format!("{} {}\n", MAGIC, archive.version).into_bytes();
+	for (path, contents) in &archive.files {
+		let path = path.components().map(|it| it.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+		bytes.extend(format!("file {} {}\n", contents.len(), path).into_bytes());
+		bytes.extend(contents);
+		bytes.push(b'\n');
+	}
+	bytes.extend(b"end\n");
+	bytes
+}
+
+/// Reads an archive and brings it up to the current version.
+pub fn decode(bytes: &[u8]) -> Result<Archive, String> {
+	let mut rest = bytes;
+	let header = next_line(&mut rest).ok_or("Not a trove backup")?;
+	let version = header.strip_prefix(MAGIC)
+		.and_then(|it| it.trim().parse::<u32>().ok())
+		.ok_or("Not a trove backup")?;
+	if version > VERSION {
+		return Err(format!("Backup version {} is newer than this trove reads ({})", version, VERSION));
+	}
+	let mut files = Vec::new();
+	loop {
+		let line = next_line(&mut rest).ok_or("Backup ends early")?;
+		if line == "end" {
+			break;
+		}
+		let (len, path) = line.strip_prefix("file ")
+			.and_then(|it| it.split_once(' '))
+			.ok_or_else(|| format!("Bad entry {:?}", line))?;
+		let len = len.parse::<usize>().map_err(|_| format!("Bad length in {:?}", line))?;
+		let path = PathBuf::from(path);
+		if !path.components().all(|it| matches!(it, Component::Normal(_))) {
+			return Err(format!("Backup path {} leaves the data directory", path.display()));
+		}
+		if rest.len() < len + 1 || rest[len] != b'\n' {
+			return Err(format!("Backup ends inside {}", path.display()));
+		}
+		files.push((path, rest[..len].to_vec()));
+		rest = &rest[len + 1..];
+	}
+	migrate(Archive { version, files })
+}
+
+/// Upgrades an archive written by an older trove. Version 1 is the first
+/// so there is nothing to upgrade yet.
+fn migrate(archive: Archive) -> Result<Archive, String> {
+	match archive.version {
+		VERSION => Ok(archive),
+		version => Err(format!("No migration from backup version {}", version)),
+	}
+}
+
+fn next_line<'a>(rest: &mut &'a [u8]) -> Option<&'a str> {
+	let end = rest.iter().position(|it| *it == b'\n')?;
+	let line = std::str::from_utf8(&rest[..end]).ok()?;
+	*rest = &rest[end + 1..];
+	Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::Archive;
+
+	#[test]
+	fn decode_reads_what_encode_writes() {
+		let files = vec![
+			(PathBuf::from("annex.json"), b"{\"squads\": {}}\n".to_vec()),
+			(PathBuf::from("echo/0001"), vec![0, 10, 255]),
+		];
+		let archive = Archive { version: super::VERSION, files };
+		assert_eq!(Ok(archive.clone()), super::decode(&super::encode(&archive)));
+	}
+
+	#[test]
+	fn decode_rejects_newer_versions_and_escaping_paths() {
+		assert!(super::decode(b"TROVE-BACKUP 99\nend\n").unwrap_err().contains("newer"));
+		assert!(super::decode(b"TROVE-BACKUP 1\nfile 1 ../x\na\nend\n").unwrap_err().contains("leaves"));
+	}
+}
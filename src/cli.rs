@@ -0,0 +1,358 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use chad_core::core::Squad;
+use chrono::{Local, NaiveDate};
+
+use crate::{backup, compute, export, positions, quotes};
+use crate::compute::Side;
+use crate::vault::{SquadAnnex, Trade, Vault};
+
+pub const USAGE: &str = "\
+Usage: trove [--data-dir <dir>] [--profile <name>] [command]
+
+With no command trove opens the squad picker. --data-dir points trove at
+a store other than the one under home, as does TROVE_DATA_DIR when the
+flag is absent. --profile picks the profile whose squads to use and must
+name a profile that exists. Without it trove uses the profile last picked
+in the squad picker.
+
+Commands:
+  profiles                                    List profiles
+  add-profile <name>                          Add a profile
+  squads                                      List squads
+  drift <squad> [--json] [--out <file>]       Print the drift of each member
+  set-price <squad> <symbol> <price>          Set the price of a member
+  add-lot <squad> <symbol> <account> <shares> Add a lot to a member
+  set-unspent <squad> <amount> [account]      Set the unspent cash of a squad
+                                              or of one of its accounts
+  import-prices [file] [--dry-run]            Import prices from a quotes csv
+  import-lots <squad> <file> [--replace] [--columns <file>] [--dry-run]
+                                              Update lots from a positions csv
+  record-trade <squad> <buy|sell> <symbol> <account> <shares> <price> [date]
+                                              Apply a trade to lots and unspent cash
+  trades <squad>                              List recorded trades
+  export <squad> [--csv] [--out <file>]       Print members, lots and cash
+                                              as json or csv
+  history <squad>                             List changes to a squad
+  undo                                        Revert the most recent edit
+  redo                                        Apply the most recently undone edit
+  backup <file>                               Write the data directory to
+                                              one archive file
+  restore <file> [--force]                    Restore the data directory from
+                                              an archive
+  help                                        Print this message
+
+import-prices prints a symbol quoted more than once as a conflict and
+leaves its prices alone.
+
+import-lots merges positions into the lots of each account in the file
+unless --replace also removes lots the file leaves out of those accounts.
+Its columns are named by a json object with account, symbol, shares and
+optional cost keys whose values are header names, read from --columns or
+positions-columns.json in the data directory. Without one the headers
+are account, symbol, shares and cost. Fewer shares trim lots oldest
+first and more shares add a lot. A position whose cost cannot be split
+among its lots is printed as a conflict and left alone.
+
+set-unspent with an account changes only that account's cash. Until the
+cash is split by account it all sits with the account holding the most
+value, and only that account can be set; set the squad's unspent to 0 or
+split it in the squad picker to set others.
+
+restore fills an empty or missing data directory. With --force it first
+moves the current one aside.
+
+A squad is named by its name or id. drift prints its full report and
+then exits with status 1 when any member is outside its tolerance band.";
+
+pub fn run(vault: &Vault, args: &[String]) -> Result<(), Box<dyn Error>> {
+	let command = args[0].as_str();
+	let params = &args[1..];
+	match command {
+		"profiles" => {
+			let current = vault.profile();
+			for profile in vault.profiles() {
+				let marker = if profile == current { "*" } else { " " };
+				println!("{} {}", marker, profile);
+			}
+		}
+		"add-profile" => vault.add_profile(param(params, 0, "name")?)?,
+		"squads" => {
+			for squad in vault.squads() {
+				println!("{}\t{}\t{} members\t{:.2} unspent", squad.id, squad.name, squad.members.len(), squad.unspent);
+			}
+		}
+		"drift" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			let annex = vault.squad_annex(squad.id);
+			if params.iter().any(|it| it == "--json") {
+				let json = serde_json::to_string_pretty(&export::drift_json(&squad, &annex))?;
+				match flag_value(params, "--out") {
+					None => println!("{}", json),
+					Some(path) => fs::write(path, json)?,
+				}
+			} else {
+				print_drift(&squad, &annex);
+			}
+			let out_of_band = compute::drifts(&squad, &annex).iter().filter(|it| it.out_of_band == Some(true)).count();
+			if out_of_band > 0 {
+				// Out of band is a finding rather than a failure so the report
+				// stands as is and only the exit status tells scripts.
+				eprintln!("{} member(s) of {} out of band", out_of_band, squad.name);
+				process::exit(1);
+			}
+		}
+		"set-price" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			let symbol = param(params, 1, "symbol")?.to_uppercase();
+			let price = parse_amount(param(params, 2, "price")?)?;
+			if !squad.prices.contains_key(&symbol) {
+				return Err(format!("No member {} in {}", symbol, squad.name).into());
+			}
+			vault.set_price(squad.id, &symbol, price)?;
+		}
+		"add-lot" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			let symbol = param(params, 1, "symbol")?.to_uppercase();
+			let account = param(params, 2, "account")?;
+			let shares = parse_amount(param(params, 3, "shares")?)?;
+			if !squad.members.iter().any(|it| it.symbol == symbol) {
+				return Err(format!("No member {} in {}", symbol, squad.name).into());
+			}
+			let lot_id = rand::random();
+			vault.add_lot(squad.id, lot_id, &symbol, account, shares)?;
+			println!("{}", lot_id);
+		}
+		"set-unspent" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			let unspent = parse_amount(param(params, 1, "amount")?)?;
+			match params.get(2) {
+				None => vault.set_unspent(squad.id, unspent)?,
+				Some(account) => {
+					let annex = vault.squad_annex(squad.id);
+					let cash = compute::squad_cash(&squad, &annex);
+					let cash = compute::with_account_cash(&cash, !annex.cash.is_empty(), account, unspent)?;
+					vault.set_account_cash(squad.id, cash)?;
+				}
+			}
+		}
+		"import-prices" => {
+			let dry_run = params.iter().any(|it| it == "--dry-run");
+			let path = params.iter().find(|it| !it.starts_with("--"))
+				.map(PathBuf::from)
+				.unwrap_or_else(|| vault.data_dir().join(quotes::QUOTES_FILE));
+			let quotes = quotes::parse_csv(&fs::read_to_string(&path)?)?;
+			let import = quotes::plan_import(&vault.squads(), &quotes);
+			for change in &import.changes {
+				println!("{}\t{}\t{} -> {}\t{}", change.squad_name, change.symbol, change.old_price, change.new_price, change.date.as_deref().unwrap_or(""));
+				if !dry_run {
+					vault.set_price(change.squad_id, &change.symbol, change.new_price)?;
+				}
+			}
+			for symbol in &import.unknown {
+				println!("unknown\t{}", symbol);
+			}
+			for conflict in &import.conflicts {
+				println!("conflict\t{}", conflict);
+			}
+		}
+		"import-lots" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			let path = param(params, 1, "file")?;
+			let dry_run = params.iter().any(|it| it == "--dry-run");
+			let mode = if params.iter().any(|it| it == "--replace") { positions::Mode::Replace } else { positions::Mode::Merge };
+			let columns_path = flag_value(params, "--columns")
+				.map(PathBuf::from)
+				.unwrap_or_else(|| vault.data_dir().join(positions::COLUMNS_FILE));
+			let columns = match fs::read_to_string(&columns_path) {
+				Ok(json) => serde_json::from_str::<positions::Columns>(&json)?,
+				Err(_) if flag_value(params, "--columns").is_none() => positions::Columns::default(),
+				Err(error) => return Err(format!("Cannot read {}: {}", columns_path.display(), error).into()),
+			};
+			let found = positions::parse_csv(&fs::read_to_string(path)?, &columns)?;
+			let members = squad.members.iter().map(|it| it.symbol.to_owned()).collect::<Vec<_>>();
+			let today = Local::now().naive_local().date();
+			let import = positions::plan_import(&squad.lots, &vault.squad_annex(squad.id), &members, &found, mode, today);
+			for change in &import.changes {
+				match change {
+					positions::LotChange::Add { symbol, account, shares, .. } => println!("add\t{}\t{}\t{}", account, symbol, shares),
+					positions::LotChange::Update { symbol, account, old_shares, shares, .. } => println!("update\t{}\t{}\t{} -> {}", account, symbol, old_shares, shares),
+					positions::LotChange::Remove { symbol, account, shares, .. } => println!("remove\t{}\t{}\t{}", account, symbol, shares),
+				}
+			}
+			for symbol in &import.unknown {
+				println!("unknown\t{}", symbol);
+			}
+			for conflict in &import.conflicts {
+				println!("conflict\t{}", conflict);
+			}
+			if !dry_run {
+				vault.import_lots(squad.id, &import.changes)?;
+			}
+		}
+		"record-trade" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			let side = match param(params, 1, "buy|sell")?.to_lowercase().as_str() {
+				"buy" => Side::Buy,
+				"sell" => Side::Sell,
+				side => return Err(format!("Bad side {}", side).into()),
+			};
+			let symbol = param(params, 2, "symbol")?.to_uppercase();
+			let account = param(params, 3, "account")?.to_string();
+			let shares = parse_amount(param(params, 4, "shares")?)?;
+			let price = parse_amount(param(params, 5, "price")?)?;
+			let date = match params.get(6) {
+				None => Local::now().naive_local().date(),
+				Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Bad date {}", date))?,
+			};
+			if !squad.members.iter().any(|it| it.symbol == symbol) {
+				return Err(format!("No member {} in {}", symbol, squad.name).into());
+			}
+			vault.record_trade(&squad, Trade { side, symbol, account, shares, price, date })?;
+		}
+		"trades" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			println!("date\tside\tsymbol\taccount\tshares\tprice\tamount");
+			for trade in vault.squad_annex(squad.id).trades {
+				let side = match trade.side {
+					Side::Buy => "buy",
+					Side::Sell => "sell",
+				};
+				println!(
+					"{}\t{}\t{}\t{}\t{}\t{:.2}\t{:.2}",
+					trade.date.format("%Y-%m-%d"),
+					side,
+					trade.symbol,
+					trade.account,
+					trade.shares,
+					trade.price,
+					trade.amount(),
+				);
+			}
+		}
+		"export" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			let annex = vault.squad_annex(squad.id);
+			let text = if params.iter().any(|it| it == "--csv") {
+				export::squad_csv(&squad, &annex)
+			} else {
+				serde_json::to_string_pretty(&export::squad_json(&squad, &annex))?
+			};
+			match flag_value(params, "--out") {
+				None => print!("{}", text),
+				Some(path) => fs::write(path, text)?,
+			}
+		}
+		"history" => {
+			let squad = find_squad(vault, param(params, 0, "squad")?)?;
+			println!("time\twho\tchange");
+			for change in vault.history(squad.id) {
+				println!("{}\t{}\t{}", change.at.format("%Y-%m-%d %H:%M:%S"), change.who, change.describe());
+			}
+		}
+		"undo" => match vault.undo()? {
+			0 => return Err("Nothing to undo".into()),
+			count => println!("Undid {} change(s)", count),
+		},
+		"redo" => match vault.redo()? {
+			0 => return Err("Nothing to redo".into()),
+			count => println!("Redid {} change(s)", count),
+		},
+		"help" | "--help" | "-h" => println!("{}", USAGE),
+		_ => return Err(format!("Unknown command {}\n\n{}", command, USAGE).into()),
+	}
+	Ok(())
+}
+
+fn print_drift(squad: &Squad, annex: &SquadAnnex) {
+	println!("symbol\trank\ttarget%\tmarket\ttarget\tdrift\ttrade_shares\tband");
+	let reports = compute::drifts(squad, annex);
+	for report in &reports {
+		let drift_shares = match report.drift_shares() {
+			None => "?".to_string(),
+			Some(shares) => format!("{:.2}", -shares),
+		};
+		let band = match report.out_of_band {
+			None => "-",
+			Some(true) => "out",
+			Some(false) => "in",
+		};
+		println!(
+			"{}\t{}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t{}\t{}",
+			report.symbol(),
+			report.rank,
+			report.target_portion * 100.0,
+			report.market_value,
+			report.target_value,
+			report.drift_amount(),
+			drift_shares,
+			band,
+		);
+	}
+	let market_value = reports.iter().map(|it| it.market_value).sum::<f64>();
+	println!("unspent\t{:.2}", squad.unspent);
+	println!("total\t{:.2}", market_value + squad.unspent);
+}
+
+/// Runs the commands that read or replace the data directory as files.
+pub fn run_archive(data_dir: &Path, args: &[String]) -> Result<(), Box<dyn Error>> {
+	let params = &args[1..];
+	let path = param(params, 0, "file")?;
+	match args[0].as_str() {
+		"backup" => {
+			let archive = backup::snapshot(data_dir)?;
+			fs::write(path, backup::encode(&archive))?;
+			println!("Wrote {} file(s) to {}", archive.files.len(), path);
+		}
+		_ => {
+			let archive = backup::decode(&fs::read(path)?)?;
+			let is_empty = fs::read_dir(data_dir).map(|mut it| it.next().is_none()).unwrap_or(true);
+			if !is_empty {
+				if !params.iter().any(|it| it == "--force") {
+					return Err(format!("{} is not empty. Pass --force to move it aside", data_dir.display()).into());
+				}
+				let aside = PathBuf::from(format!("{}-before-restore-{}", data_dir.display(), Local::now().format("%Y%m%d%H%M%S")));
+				fs::rename(data_dir, &aside)?;
+				println!("Moved {} to {}", data_dir.display(), aside.display());
+			}
+			backup::unpack(&archive, data_dir)?;
+			println!("Restored {} file(s) to {}", archive.files.len(), data_dir.display());
+		}
+	}
+	Ok(())
+}
+
+fn find_squad(vault: &Vault, name: &str) -> Result<Squad, Box<dyn Error>> {
+	let squads = vault.squads();
+	let id = name.parse::<u64>().ok();
+	squads.into_iter()
+		.find(|it| Some(it.id) == id || it.name.eq_ignore_ascii_case(name))
+		.ok_or_else(|| format!("No squad {}", name).into())
+}
+
+fn param<'a>(params: &'a [String], index: usize, name: &str) -> Result<&'a str, Box<dyn Error>> {
+	params.get(index).map(|it| it.as_str()).ok_or_else(|| format!("Missing <{}>\n\n{}", name, USAGE).into())
+}
+
+/// Removes a flag and its value from the arguments and returns the value.
+pub fn take_flag(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, Box<dyn Error>> {
+	let index = match args.iter().position(|it| it == flag) {
+		None => return Ok(None),
+		Some(index) => index,
+	};
+	let value = args.get(index + 1).cloned().ok_or_else(|| format!("Missing value for {}\n\n{}", flag, USAGE))?;
+	args.drain(index..index + 2);
+	Ok(Some(value))
+}
+
+fn flag_value<'a>(params: &'a [String], flag: &str) -> Option<&'a str> {
+	let index = params.iter().position(|it| it == flag)?;
+	params.get(index + 1).map(|it| it.as_str())
+}
+
+fn parse_amount(text: &str) -> Result<f64, Box<dyn Error>> {
+	text.trim_start_matches('$').parse::<f64>().map_err(|_| format!("Bad number {}", text).into())
+}
@@ -1,2 +1,747 @@
+use std::collections::BTreeMap;
 
+use chad_core::core::{Lot, Squad, SquadMember};
+use chrono::{Datelike, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
 
+use crate::vault::{Band, LotBasis, SquadAnnex, Weighting};
+
+/// Drift of a member from its target, like chad's drift report but with
+/// target portions from the squad's weighting.
+#[derive(Clone, Debug)]
+pub struct Drift {
+	pub member: SquadMember,
+	pub rank: usize,
+	pub price: f64,
+	pub target_portion: f64,
+	pub market_value: f64,
+	pub target_value: f64,
+	/// Whether the drift is outside the member's band or None when the
+	/// member has no band.
+	pub out_of_band: Option<bool>,
+}
+
+impl Drift {
+	pub fn symbol(&self) -> &str { &self.member.symbol }
+	pub fn drift_amount(&self) -> f64 { self.market_value - self.target_value }
+	pub fn drift_shares(&self) -> Option<f64> {
+		if self.price > 0.0 { Some(self.drift_amount() / self.price) } else { None }
+	}
+}
+
+/// Drift of each member of a squad in rank order, highest rank first.
+/// Ranks and rank portions stay with their positions while members move
+/// into the squad's order.
+pub fn drifts(squad: &Squad, annex: &SquadAnnex) -> Vec<Drift> {
+	let reports = squad.drift_reports().into_iter().rev().collect::<Vec<_>>();
+	let ranked = rank_order(&reports.iter().map(|it| it.symbol()).collect::<Vec<_>>(), &annex.order)
+		.into_iter()
+		.map(|i| &reports[i])
+		.collect::<Vec<_>>();
+	let symbols = ranked.iter().map(|it| it.symbol()).collect::<Vec<_>>();
+	let rank_portions = reports.iter().map(|it| it.target_portion).collect::<Vec<_>>();
+	let portions = target_portions(&symbols, &rank_portions, &annex.weighting);
+	let total = reports.iter().map(|it| it.market_value).sum::<f64>() + squad.unspent;
+	ranked.iter().zip(&reports).zip(portions).map(|((report, position), target_portion)| {
+		let target_value = total * target_portion;
+		let band = annex.member_band(report.symbol());
+		Drift {
+			member: report.member.to_owned(),
+			rank: position.rank,
+			price: squad.prices.get(report.symbol()).cloned().unwrap_or(0.0),
+			target_portion,
+			market_value: report.market_value,
+			target_value,
+			out_of_band: if band.is_set() { Some(is_out_of_band(&band, report.market_value, target_value, total)) } else { None },
+		}
+	}).collect()
+}
+
+/// A member is out of band when its drift passes either limit of the band.
+pub fn is_out_of_band(band: &Band, market_value: f64, target_value: f64, total: f64) -> bool {
+	let drift = (market_value - target_value).abs();
+	let past_absolute = band.absolute.map(|limit| total > 0.0 && drift / total * 100.0 > limit);
+	let past_relative = band.relative.map(|limit| if target_value > 0.0 { drift / target_value * 100.0 > limit } else { drift > 0.0 });
+	past_absolute.unwrap_or(false) || past_relative.unwrap_or(false)
+}
+
+/// Indices of symbols with those in the order first and the rest after
+/// them in their original order.
+pub fn rank_order(symbols: &[&str], order: &[String]) -> Vec<usize> {
+	let mut indices = order.iter()
+		.filter_map(|symbol| symbols.iter().position(|it| it == symbol))
+		.collect::<Vec<_>>();
+	for i in 0..symbols.len() {
+		if !indices.contains(&i) {
+			indices.push(i);
+		}
+	}
+	indices
+}
+
+/// Moves a symbol up (negative offset) or down the order.
+pub fn move_member(order: &[String], symbol: &str, offset: i32) -> Vec<String> {
+	let mut order = order.to_vec();
+	if let Some(from) = order.iter().position(|it| it == symbol) {
+		let to = (from as i32 + offset).clamp(0, order.len() as i32 - 1) as usize;
+		let symbol = order.remove(from);
+		order.insert(to, symbol);
+	}
+	order
+}
+
+/// Target portions of members in rank order. Rank portions are the ones
+/// chad gives each rank.
+pub fn target_portions(symbols: &[&str], rank_portions: &[f64], weighting: &Weighting) -> Vec<f64> {
+	match weighting {
+		Weighting::Rank => rank_portions.to_vec(),
+		Weighting::Curve(ratio) => {
+			let weights = (0..symbols.len()).map(|i| ratio.powi(i as i32)).collect::<Vec<_>>();
+			let sum = weights.iter().sum::<f64>();
+			weights.into_iter().map(|it| if sum > 0.0 { it / sum } else { 0.0 }).collect()
+		}
+		Weighting::Percent(percents) => symbols.iter()
+			.map(|it| percents.get(*it).cloned().unwrap_or(0.0) / 100.0)
+			.collect(),
+	}
+}
+
+/// Portions as percents with two decimals. The last takes what rounding
+/// leaves so they still total 100%.
+pub fn rounded_percents(portions: &[f64]) -> Vec<f64> {
+	let round = |it: f64| (it * 100.0).round() / 100.0;
+	let mut percents = portions.iter().map(|it| round(it * 100.0)).collect::<Vec<_>>();
+	if let Some((last, others)) = percents.split_last_mut() {
+		*last = round(100.0 - others.iter().sum::<f64>());
+	}
+	percents
+}
+
+/// The total of percent targets when it is not 100%, as happens after a
+/// member joins without a percent. None for other weightings.
+pub fn broken_percent_total(weighting: &Weighting, symbols: &[&str]) -> Option<f64> {
+	match weighting {
+		Weighting::Percent(percents) => {
+			let total = symbols.iter().map(|it| percents.get(*it).cloned().unwrap_or(0.0)).sum::<f64>();
+			if (total - 100.0).abs() < 0.01 { None } else { Some(total) }
+		}
+		_ => None,
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Holding {
+	pub symbol: String,
+	pub price: f64,
+	pub target_portion: f64,
+	/// Shares held in each account.
+	pub accounts: BTreeMap<String, f64>,
+	/// Shares in each account that may be sold.
+	pub sellable: BTreeMap<String, f64>,
+}
+
+impl Holding {
+	pub fn shares(&self) -> f64 { self.accounts.values().sum() }
+	pub fn market_value(&self) -> f64 { self.shares() * self.price }
+	pub fn target_value(&self, total: f64) -> f64 { total * self.target_portion }
+	pub fn drift_amount(&self, total: f64) -> f64 { self.market_value() - self.target_value(total) }
+}
+
+/// Holdings of a squad in rank order, highest rank first.
+pub fn holdings(squad: &Squad, annex: &SquadAnnex) -> Vec<Holding> {
+	drifts(squad, annex).into_iter().map(|drift| {
+		let symbol = drift.symbol().to_string();
+		let mut accounts = BTreeMap::new();
+		for lot in squad.lots.iter().filter(|it| it.symbol == symbol) {
+			*accounts.entry(lot.account.to_owned()).or_insert(0.0) += lot.shares;
+		}
+		Holding {
+			price: drift.price,
+			target_portion: drift.target_portion,
+			sellable: accounts.clone(),
+			accounts,
+			symbol,
+		}
+	}).collect()
+}
+
+/// Unspent cash of a squad by account. A squad whose cash was never split
+/// by account has it all in the account holding the most market value.
+pub fn squad_cash(squad: &Squad, annex: &SquadAnnex) -> BTreeMap<String, f64> {
+	if !annex.cash.is_empty() {
+		return annex.cash.to_owned();
+	}
+	let mut values = BTreeMap::new();
+	for lot in &squad.lots {
+		let price = squad.prices.get(&lot.symbol).cloned().unwrap_or(0.0);
+		*values.entry(lot.account.to_owned()).or_insert(0.0) += lot.shares * price;
+	}
+	let account = values.into_iter()
+		.max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("Comparable value"))
+		.map(|(account, _)| account)
+		.unwrap_or_default();
+	let mut cash = BTreeMap::new();
+	cash.insert(account, squad.unspent);
+	cash
+}
+
+/// Cash by account after giving one account a new amount. Cash that was
+/// never split by account sits wholly in one account and only that account
+/// can be set, since how much of it belongs to the others is unknown.
+pub fn with_account_cash(cash: &BTreeMap<String, f64>, is_split: bool, account: &str, amount: f64) -> Result<BTreeMap<String, f64>, String> {
+	let mut cash = cash.to_owned();
+	if !is_split {
+		let unsplit = cash.values().sum::<f64>();
+		let holder = cash.keys().next().cloned().unwrap_or_default();
+		if unsplit != 0.0 && holder != account {
+			return Err(format!("Unspent cash is not split by account and {} holds none of it. Split it by account first", account));
+		}
+		cash.clear();
+	}
+	cash.insert(account.to_string(), amount);
+	Ok(cash)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Term { Short, Long }
+
+/// Lots held for more than a year are long-term.
+pub fn term(acquired: NaiveDate, today: NaiveDate) -> Term {
+	match acquired.checked_add_months(Months::new(12)) {
+		Some(anniversary) if today > anniversary => Term::Long,
+		_ => Term::Short,
+	}
+}
+
+/// Unrealized gain of a lot at a price or None without a cost basis.
+pub fn lot_gain(lot: &Lot, basis: &LotBasis, price: f64) -> Option<f64> {
+	basis.cost.map(|cost| lot.shares * price - cost)
+}
+
+/// Unrealized gains of a group of lots by holding period.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Gains {
+	pub short_term: f64,
+	pub long_term: f64,
+	/// Gains of lots with a cost basis but no acquisition date.
+	pub undated: f64,
+	/// Lots without a cost basis.
+	pub unknown_count: usize,
+}
+
+impl Gains {
+	fn add(&mut self, gain: Option<f64>, acquired: Option<NaiveDate>, today: NaiveDate) {
+		match (gain, acquired) {
+			(None, _) => self.unknown_count += 1,
+			(Some(gain), None) => self.undated += gain,
+			(Some(gain), Some(acquired)) => match term(acquired, today) {
+				Term::Short => self.short_term += gain,
+				Term::Long => self.long_term += gain,
+			},
+		}
+	}
+}
+
+pub fn gains(lots: &[&Lot], annex: &SquadAnnex, price: f64, today: NaiveDate) -> Gains {
+	let mut gains = Gains::default();
+	for lot in lots {
+		let basis = annex.lots.get(&lot.id).cloned().unwrap_or_default();
+		gains.add(lot_gain(lot, &basis, price), basis.acquired, today);
+	}
+	gains
+}
+
+/// Which lots a sell takes shares from first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LotPolicy {
+	Fifo,
+	Lifo,
+	HighestCost,
+	/// Lots at a loss, then long-term and then short-term gains, smallest
+	/// gain first.
+	LossesFirst,
+	/// Only long-term lots, oldest first.
+	LongTermOnly,
+}
+
+/// Shares taken from a lot by a sell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LotSale {
+	pub lot_id: u64,
+	pub symbol: String,
+	pub account: String,
+	pub shares: f64,
+	pub acquired: Option<NaiveDate>,
+	/// Realized gain or None when the lot has no cost basis.
+	pub gain: Option<f64>,
+}
+
+/// Shares a policy allows to be sold in each account.
+pub fn sellable(symbol: &str, lots: &[Lot], annex: &SquadAnnex, policy: LotPolicy, today: NaiveDate) -> BTreeMap<String, f64> {
+	let mut sellable = BTreeMap::new();
+	for lot in lots.iter().filter(|it| it.symbol == symbol && is_eligible(it, annex, policy, today)) {
+		*sellable.entry(lot.account.to_owned()).or_insert(0.0) += lot.shares;
+	}
+	sellable
+}
+
+fn is_eligible(lot: &Lot, annex: &SquadAnnex, policy: LotPolicy, today: NaiveDate) -> bool {
+	match policy {
+		LotPolicy::LongTermOnly => {
+			let acquired = annex.lots.get(&lot.id).and_then(|it| it.acquired);
+			acquired.map(|it| term(it, today)) == Some(Term::Long)
+		}
+		_ => true,
+	}
+}
+
+/// Picks the lots each sell order takes its shares from.
+pub fn lot_sales(orders: &[Order], lots: &[Lot], annex: &SquadAnnex, policy: LotPolicy, today: NaiveDate) -> Vec<LotSale> {
+	let mut sales = Vec::new();
+	for order in orders.iter().filter(|it| it.side == Side::Sell) {
+		let mut candidates = lots.iter()
+			.filter(|it| it.symbol == order.symbol && it.account == order.account && it.shares > 0.0)
+			.filter(|it| is_eligible(it, annex, policy, today))
+			.map(|it| (it, annex.lots.get(&it.id).cloned().unwrap_or_default()))
+			.map(|(lot, basis)| (lot_key(lot, &basis, order.price, policy, today), lot, basis))
+			.collect::<Vec<_>>();
+		candidates.sort_by(|(a, _, _), (b, _, _)| a.0.cmp(&b.0).then(a.1.partial_cmp(&b.1).expect("Comparable key")));
+		let mut remaining = order.shares;
+		for (_, lot, basis) in candidates {
+			if remaining <= SHARE_TOLERANCE {
+				break;
+			}
+			let shares = remaining.min(lot.shares);
+			remaining -= shares;
+			sales.push(LotSale {
+				lot_id: lot.id,
+				symbol: lot.symbol.to_owned(),
+				account: lot.account.to_owned(),
+				shares,
+				acquired: basis.acquired,
+				gain: basis.cost.map(|cost| shares * (order.price - cost / lot.shares)),
+			});
+		}
+	}
+	sales
+}
+
+/// Share counts closer than this are the same. Fractional shares pick up
+/// rounding as trades add and subtract them.
+pub const SHARE_TOLERANCE: f64 = 1e-9;
+
+/// What a sell leaves of a lot it takes shares from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LotLeft {
+	/// The lot keeps these shares and, when it has one, its basis with the
+	/// cost scaled down to them.
+	Kept { lot_id: u64, shares: f64, basis: Option<LotBasis> },
+	Emptied { lot_id: u64 },
+}
+
+/// Takes a recorded sell from an account's lots, oldest first.
+pub fn sell_lots(order: &Order, lots: &[Lot], annex: &SquadAnnex, today: NaiveDate) -> Result<Vec<LotLeft>, String> {
+	let held = lots.iter()
+		.filter(|it| it.symbol == order.symbol && it.account == order.account)
+		.map(|it| it.shares)
+		.sum::<f64>();
+	if held + SHARE_TOLERANCE < order.shares {
+		return Err(format!("Only {} shares of {} in {}", held, order.symbol, order.account));
+	}
+	let lefts = lot_sales(std::slice::from_ref(order), lots, annex, LotPolicy::Fifo, today).into_iter()
+		.map(|sale| {
+			let lot = lots.iter().find(|it| it.id == sale.lot_id).expect("Sold lot");
+			let shares = lot.shares - sale.shares;
+			if shares > SHARE_TOLERANCE {
+				let basis = annex.lots.get(&lot.id).map(|basis| LotBasis { cost: basis.cost.map(|it| it * shares / lot.shares), ..*basis });
+				LotLeft::Kept { lot_id: lot.id, shares, basis }
+			} else {
+				LotLeft::Emptied { lot_id: lot.id }
+			}
+		})
+		.collect();
+	Ok(lefts)
+}
+
+/// Sort key of a lot under a policy. Lots missing what the policy sorts
+/// by go last.
+fn lot_key(lot: &Lot, basis: &LotBasis, price: f64, policy: LotPolicy, today: NaiveDate) -> (u8, f64) {
+	let days = basis.acquired.map(|it| it.num_days_from_ce() as f64);
+	let unit_cost = basis.cost.map(|it| it / lot.shares);
+	match policy {
+		LotPolicy::Fifo | LotPolicy::LongTermOnly => (days.is_none() as u8, days.unwrap_or(0.0)),
+		LotPolicy::Lifo => (days.is_none() as u8, -days.unwrap_or(0.0)),
+		LotPolicy::HighestCost => (unit_cost.is_none() as u8, -unit_cost.unwrap_or(0.0)),
+		LotPolicy::LossesFirst => match unit_cost {
+			None => (3, 0.0),
+			Some(unit_cost) => {
+				let unit_gain = price - unit_cost;
+				let category = if unit_gain < 0.0 {
+					0
+				} else if basis.acquired.map(|it| term(it, today)) == Some(Term::Long) {
+					1
+				} else {
+					2
+				};
+				(category, unit_gain)
+			}
+		},
+	}
+}
+
+/// Realized gains of lot sales by holding period.
+pub fn realized_gains(sales: &[LotSale], today: NaiveDate) -> Gains {
+	let mut gains = Gains::default();
+	for sale in sales {
+		gains.add(sale.gain, sale.acquired, today);
+	}
+	gains
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Side { Buy, Sell }
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Order {
+	pub side: Side,
+	pub symbol: String,
+	pub account: String,
+	pub shares: f64,
+	pub price: f64,
+}
+
+impl Order {
+	pub fn amount(&self) -> f64 { self.shares * self.price }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+	/// Sell overweight holdings to fund buys of the most underweight.
+	SellAndBuy,
+	/// Never sell. Unspent cash goes to underweight holdings in rank order.
+	CashOnly,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+	pub mode: Mode,
+	pub orders: Vec<Order>,
+	pub after: Vec<Holding>,
+	pub total: f64,
+	/// Cash left in each account after the trades.
+	pub cash: BTreeMap<String, f64>,
+}
+
+impl Plan {
+	pub fn leftover(&self) -> f64 {
+		self.cash.values().sum()
+	}
+
+	/// Holdings still over target by at least a share after the trades.
+	pub fn overweight(&self) -> Vec<&Holding> {
+		self.after.iter()
+			.filter(|it| it.price > 0.0 && it.drift_amount(self.total) >= it.price)
+			.collect()
+	}
+}
+
+/// Plans whole-share orders that bring holdings toward their targets.
+/// Holdings arrive in rank order. Cash never moves between accounts so
+/// every order is assigned to an account. Any sells come first and their
+/// proceeds fund buys in the same account.
+pub fn plan(holdings: &[Holding], cash: &BTreeMap<String, f64>, mode: Mode) -> Plan {
+	let total = holdings.iter().map(Holding::market_value).sum::<f64>() + cash.values().sum::<f64>();
+	let mut after = holdings.to_vec();
+	let mut cash = cash.to_owned();
+	let mut orders = Vec::new();
+	if mode == Mode::SellAndBuy {
+		for holding in after.iter_mut().filter(|it| it.price > 0.0) {
+			let shares = (holding.drift_amount(total) / holding.price).floor();
+			if shares >= 1.0 {
+				sell(holding, shares, &mut cash, &mut orders);
+			}
+		}
+	}
+	let mut underweight = (0..after.len())
+		.filter(|&i| after[i].price > 0.0 && after[i].drift_amount(total) < 0.0)
+		.collect::<Vec<_>>();
+	if mode == Mode::SellAndBuy {
+		underweight.sort_by(|&a, &b| {
+			let a = after[a].drift_amount(total);
+			let b = after[b].drift_amount(total);
+			a.partial_cmp(&b).expect("Comparable drift")
+		});
+	}
+	for &i in &underweight {
+		let holding = &mut after[i];
+		let shares = (-holding.drift_amount(total) / holding.price).floor();
+		if shares >= 1.0 {
+			buy(holding, shares, &mut cash, &mut orders);
+		}
+	}
+	// Spend what is left one share at a time where a share still
+	// brings a holding closer to its target than leaving it short.
+	loop {
+		let next = underweight.iter().cloned()
+			.filter(|&i| {
+				let holding = &after[i];
+				-holding.drift_amount(total) > holding.price / 2.0 && cash.values().any(|it| *it >= holding.price)
+			})
+			.max_by(|&a, &b| {
+				let a = -after[a].drift_amount(total);
+				let b = -after[b].drift_amount(total);
+				a.partial_cmp(&b).expect("Comparable drift")
+			});
+		match next {
+			None => break,
+			Some(i) => buy(&mut after[i], 1.0, &mut cash, &mut orders),
+		}
+	}
+	Plan { mode, orders, after, total, cash }
+}
+
+/// Sells from the accounts holding the most shares first without selling
+/// more than is sellable in each.
+fn sell(holding: &mut Holding, shares: f64, cash: &mut BTreeMap<String, f64>, orders: &mut Vec<Order>) {
+	let mut accounts = holding.accounts.iter().map(|(account, shares)| (account.to_owned(), *shares)).collect::<Vec<_>>();
+	accounts.sort_by(|(_, a), (_, b)| b.partial_cmp(a).expect("Comparable shares"));
+	let mut remaining = shares;
+	for (account, held) in accounts {
+		let sellable = holding.sellable.get(&account).cloned().unwrap_or(0.0).min(held);
+		let sold = remaining.min(sellable.floor());
+		if sold >= 1.0 {
+			remaining -= sold;
+			*holding.accounts.get_mut(&account).expect("Account holding") -= sold;
+			*holding.sellable.get_mut(&account).expect("Account sellable") -= sold;
+			*cash.entry(account.to_owned()).or_insert(0.0) += sold * holding.price;
+			push_order(orders, Side::Sell, holding, &account, sold);
+		}
+	}
+}
+
+/// Buys in the accounts already holding the symbol first and then in
+/// whichever accounts have the most cash.
+fn buy(holding: &mut Holding, shares: f64, cash: &mut BTreeMap<String, f64>, orders: &mut Vec<Order>) {
+	let mut accounts = cash.iter().map(|(account, cash)| (account.to_owned(), *cash)).collect::<Vec<_>>();
+	accounts.sort_by(|(a_account, a_cash), (b_account, b_cash)| {
+		let a_held = holding.accounts.get(a_account).cloned().unwrap_or(0.0);
+		let b_held = holding.accounts.get(b_account).cloned().unwrap_or(0.0);
+		b_held.partial_cmp(&a_held).expect("Comparable shares")
+			.then(b_cash.partial_cmp(a_cash).expect("Comparable cash"))
+	});
+	let mut remaining = shares;
+	for (account, available) in accounts {
+		let bought = remaining.min((available / holding.price).floor());
+		if bought >= 1.0 {
+			remaining -= bought;
+			*holding.accounts.entry(account.to_owned()).or_insert(0.0) += bought;
+			*cash.get_mut(&account).expect("Account cash") -= bought * holding.price;
+			push_order(orders, Side::Buy, holding, &account, bought);
+		}
+	}
+}
+
+fn push_order(orders: &mut Vec<Order>, side: Side, holding: &Holding, account: &str, shares: f64) {
+	let existing = orders.iter_mut().find(|it| it.side == side && it.symbol == holding.symbol && it.account == account);
+	match existing {
+		Some(order) => order.shares += shares,
+		None => orders.push(Order { side, symbol: holding.symbol.to_owned(), account: account.to_string(), shares, price: holding.price }),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use chad_core::core::Lot;
+	use chrono::NaiveDate;
+
+	use crate::vault::{Band, LotBasis, SquadAnnex, Weighting};
+
+	use super::{Holding, LotLeft, LotPolicy, Mode, Order, Side, Term};
+
+	fn holding(symbol: &str, price: f64, accounts: &[(&str, f64)], target_portion: f64) -> Holding {
+		let accounts = accounts.iter().map(|(account, shares)| (account.to_string(), *shares)).collect::<BTreeMap<_, _>>();
+		Holding { symbol: symbol.to_string(), price, target_portion, sellable: accounts.clone(), accounts }
+	}
+
+	fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+		NaiveDate::from_ymd_opt(year, month, day).expect("Date")
+	}
+
+	fn cash(accounts: &[(&str, f64)]) -> BTreeMap<String, f64> {
+		accounts.iter().map(|(account, cash)| (account.to_string(), *cash)).collect()
+	}
+
+	fn order(side: Side, symbol: &str, account: &str, shares: f64, price: f64) -> Order {
+		Order { side, symbol: symbol.to_string(), account: account.to_string(), shares, price }
+	}
+
+	#[test]
+	fn rounded_percents_give_the_remainder_to_the_last() {
+		let percents = super::rounded_percents(&[1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0]);
+		assert_eq!(vec![33.33, 33.33, 33.34], percents);
+		let weighting = Weighting::Percent(["A", "B", "C"].iter().map(|it| it.to_string()).zip(percents).collect());
+		assert_eq!(None, super::broken_percent_total(&weighting, &["A", "B", "C"]));
+		assert!(super::rounded_percents(&[]).is_empty());
+	}
+
+	#[test]
+	fn curve_weights_each_rank_by_ratio() {
+		let portions = super::target_portions(&["VTI", "BND", "GLD"], &[0.5, 0.3, 0.2], &Weighting::Curve(0.5));
+		assert_eq!(vec![4.0 / 7.0, 2.0 / 7.0, 1.0 / 7.0], portions);
+		let portions = super::target_portions(&["VTI", "BND"], &[0.7, 0.3], &Weighting::Curve(1.0));
+		assert_eq!(vec![0.5, 0.5], portions);
+	}
+
+	#[test]
+	fn percent_weights_by_symbol() {
+		let percents = [("BND".to_string(), 40.0), ("VTI".to_string(), 60.0)].iter().cloned().collect();
+		let portions = super::target_portions(&["VTI", "BND", "GLD"], &[0.5, 0.3, 0.2], &Weighting::Percent(percents));
+		assert_eq!(vec![0.6, 0.4, 0.0], portions);
+	}
+
+	#[test]
+	fn rank_order_puts_ordered_symbols_first() {
+		let order = vec!["GLD".to_string(), "GONE".to_string(), "VTI".to_string()];
+		assert_eq!(vec![2, 0, 1], super::rank_order(&["VTI", "BND", "GLD"], &order));
+	}
+
+	#[test]
+	fn move_member_stays_in_bounds() {
+		let order = vec!["VTI".to_string(), "BND".to_string(), "GLD".to_string()];
+		assert_eq!(vec!["BND", "VTI", "GLD"], super::move_member(&order, "BND", -1));
+		assert_eq!(vec!["VTI", "BND", "GLD"], super::move_member(&order, "VTI", -1));
+		assert_eq!(vec!["BND", "GLD", "VTI"], super::move_member(&order, "VTI", 5));
+	}
+
+	#[test]
+	fn band_limits_are_absolute_points_or_relative_percent() {
+		let absolute = Band { absolute: Some(5.0), relative: None };
+		assert!(!super::is_out_of_band(&absolute, 240.0, 200.0, 1000.0));
+		assert!(super::is_out_of_band(&absolute, 260.0, 200.0, 1000.0));
+		let relative = Band { absolute: None, relative: Some(25.0) };
+		assert!(!super::is_out_of_band(&relative, 160.0, 200.0, 1000.0));
+		assert!(super::is_out_of_band(&relative, 140.0, 200.0, 1000.0));
+		assert!(super::is_out_of_band(&relative, 10.0, 0.0, 1000.0));
+	}
+
+	#[test]
+	fn lots_held_over_a_year_are_long_term() {
+		assert_eq!(Term::Short, super::term(date(2020, 3, 15), date(2021, 3, 15)));
+		assert_eq!(Term::Long, super::term(date(2020, 3, 15), date(2021, 3, 16)));
+		assert_eq!(Term::Long, super::term(date(2020, 2, 29), date(2021, 3, 1)));
+	}
+
+	#[test]
+	fn lot_policies_pick_lots_for_sells() {
+		let lot = |id, shares| Lot { squad_id: 1, id, symbol: "VTI".to_string(), account: "IRA".to_string(), shares };
+		let lots = vec![lot(1, 5.0), lot(2, 5.0), lot(3, 5.0)];
+		let mut annex = SquadAnnex::default();
+		annex.lots.insert(1, LotBasis { cost: Some(50.0), acquired: Some(date(2018, 1, 1)) });
+		annex.lots.insert(2, LotBasis { cost: Some(150.0), acquired: Some(date(2020, 6, 1)) });
+		annex.lots.insert(3, LotBasis { cost: Some(75.0), acquired: Some(date(2019, 1, 1)) });
+		let orders = vec![order(Side::Sell, "VTI", "IRA", 7.0, 20.0)];
+		let today = date(2020, 12, 1);
+		let picked = |policy| super::lot_sales(&orders, &lots, &annex, policy, today).iter()
+			.map(|it| (it.lot_id, it.shares))
+			.collect::<Vec<_>>();
+		assert_eq!(vec![(1, 5.0), (3, 2.0)], picked(LotPolicy::Fifo));
+		assert_eq!(vec![(2, 5.0), (3, 2.0)], picked(LotPolicy::Lifo));
+		assert_eq!(vec![(2, 5.0), (3, 2.0)], picked(LotPolicy::HighestCost));
+		assert_eq!(vec![(2, 5.0), (3, 2.0)], picked(LotPolicy::LossesFirst));
+		let sales = super::lot_sales(&orders, &lots, &annex, LotPolicy::LongTermOnly, today);
+		assert_eq!(vec![(1, 5.0), (3, 2.0)], sales.iter().map(|it| (it.lot_id, it.shares)).collect::<Vec<_>>());
+		let gains = super::realized_gains(&sales, today);
+		assert_eq!(50.0 + 10.0, gains.long_term);
+		let sellable = super::sellable("VTI", &lots, &annex, LotPolicy::LongTermOnly, today);
+		assert_eq!(Some(&10.0), sellable.get("IRA"));
+	}
+
+	#[test]
+	fn sells_empty_the_oldest_lot_and_trim_the_next() {
+		let lot = |id, shares| Lot { squad_id: 1, id, symbol: "VTI".to_string(), account: "IRA".to_string(), shares };
+		let lots = vec![lot(1, 5.0), lot(2, 5.0), lot(3, 0.3)];
+		let mut annex = SquadAnnex::default();
+		annex.lots.insert(1, LotBasis { cost: Some(150.0), acquired: Some(date(2020, 6, 1)) });
+		annex.lots.insert(2, LotBasis { cost: Some(50.0), acquired: Some(date(2018, 1, 1)) });
+		let today = date(2020, 12, 1);
+		let lefts = super::sell_lots(&order(Side::Sell, "VTI", "IRA", 7.0, 20.0), &lots, &annex, today).unwrap();
+		let kept = LotLeft::Kept { lot_id: 1, shares: 3.0, basis: Some(LotBasis { cost: Some(90.0), acquired: Some(date(2020, 6, 1)) }) };
+		assert_eq!(vec![LotLeft::Emptied { lot_id: 2 }, kept], lefts);
+		let lefts = super::sell_lots(&order(Side::Sell, "VTI", "IRA", 10.0 + 0.1 + 0.2, 20.0), &lots, &annex, today).unwrap();
+		assert_eq!(vec![LotLeft::Emptied { lot_id: 2 }, LotLeft::Emptied { lot_id: 1 }, LotLeft::Emptied { lot_id: 3 }], lefts);
+		let error = super::sell_lots(&order(Side::Sell, "VTI", "IRA", 11.0, 20.0), &lots, &annex, today);
+		assert_eq!(Err("Only 10.3 shares of VTI in IRA".to_string()), error);
+	}
+
+	#[test]
+	fn plan_sells_overweight_to_fund_underweight() {
+		let holdings = vec![holding("VTI", 10.0, &[("IRA", 10.0)], 0.5), holding("BND", 10.0, &[], 0.5)];
+		let plan = super::plan(&holdings, &cash(&[]), Mode::SellAndBuy);
+		assert_eq!(vec![
+			order(Side::Sell, "VTI", "IRA", 5.0, 10.0),
+			order(Side::Buy, "BND", "IRA", 5.0, 10.0),
+		], plan.orders);
+		assert_eq!(0.0, plan.leftover());
+		assert!(plan.after.iter().all(|it| it.drift_amount(plan.total) == 0.0));
+	}
+
+	#[test]
+	fn plan_buys_whole_shares_within_cash() {
+		let holdings = vec![holding("VTI", 30.0, &[], 1.0)];
+		let plan = super::plan(&holdings, &cash(&[("IRA", 100.0)]), Mode::SellAndBuy);
+		assert_eq!(vec![order(Side::Buy, "VTI", "IRA", 3.0, 30.0)], plan.orders);
+		assert_eq!(10.0, plan.leftover());
+	}
+
+	#[test]
+	fn cash_only_plan_buys_in_rank_order_without_selling() {
+		let holdings = vec![
+			holding("VTI", 10.0, &[], 0.4),
+			holding("BND", 10.0, &[], 0.4),
+			holding("GLD", 10.0, &[("IRA", 20.0)], 0.2),
+		];
+		let plan = super::plan(&holdings, &cash(&[("IRA", 50.0)]), Mode::CashOnly);
+		assert_eq!(vec![order(Side::Buy, "VTI", "IRA", 5.0, 10.0)], plan.orders);
+		assert_eq!(0.0, plan.leftover());
+		let overweight = plan.overweight().into_iter().map(|it| it.symbol.as_str()).collect::<Vec<_>>();
+		assert_eq!(vec!["GLD"], overweight);
+	}
+
+	#[test]
+	fn plan_keeps_cash_within_accounts() {
+		let holdings = vec![
+			holding("VTI", 10.0, &[("IRA", 10.0)], 0.5),
+			holding("BND", 10.0, &[("Roth", 1.0)], 0.5),
+		];
+		let plan = super::plan(&holdings, &cash(&[("Roth", 20.0)]), Mode::SellAndBuy);
+		assert_eq!(vec![
+			order(Side::Sell, "VTI", "IRA", 3.0, 10.0),
+			order(Side::Buy, "BND", "Roth", 2.0, 10.0),
+			order(Side::Buy, "BND", "IRA", 3.0, 10.0),
+		], plan.orders);
+		assert_eq!(0.0, plan.leftover());
+	}
+
+	#[test]
+	fn with_account_cash_needs_a_split_for_other_accounts() {
+		let split = super::with_account_cash(&cash(&[("IRA", 10.0), ("Roth", 5.0)]), true, "Roth", 8.0);
+		assert_eq!(Ok(cash(&[("IRA", 10.0), ("Roth", 8.0)])), split);
+		let holder = super::with_account_cash(&cash(&[("IRA", 15.0)]), false, "IRA", 20.0);
+		assert_eq!(Ok(cash(&[("IRA", 20.0)])), holder);
+		assert!(super::with_account_cash(&cash(&[("IRA", 15.0)]), false, "Roth", 20.0).is_err());
+		assert!(super::with_account_cash(&cash(&[("", 15.0)]), false, "Roth", 20.0).is_err());
+		let nothing_unsplit = super::with_account_cash(&cash(&[("", 0.0)]), false, "Roth", 20.0);
+		assert_eq!(Ok(cash(&[("Roth", 20.0)])), nothing_unsplit);
+	}
+
+	#[test]
+	fn broken_percent_total_counts_members_without_a_percent() {
+		let percents = Weighting::Percent(BTreeMap::from([("VTI".to_string(), 60.0), ("BND".to_string(), 40.0), ("GLD".to_string(), 10.0)]));
+		assert_eq!(None, super::broken_percent_total(&percents, &["VTI", "BND"]));
+		assert_eq!(Some(60.0), super::broken_percent_total(&percents, &["VTI", "IAU"]));
+		assert_eq!(Some(110.0), super::broken_percent_total(&percents, &["VTI", "BND", "GLD"]));
+		assert_eq!(None, super::broken_percent_total(&Weighting::Rank, &["VTI"]));
+	}
+}
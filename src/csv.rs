@@ -0,0 +1,40 @@
+/// Splits a csv line on commas outside of quotes and trims each field.
+pub fn split_line(line: &str) -> Vec<String> {
+	let mut fields = vec![String::new()];
+	let mut quoted = false;
+	let mut chars = line.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' if quoted && chars.peek() == Some(&'"') => {
+				chars.next();
+				fields.last_mut().expect("Field").push('"');
+			}
+			'"' => quoted = !quoted,
+			',' if !quoted => fields.push(String::new()),
+			c => fields.last_mut().expect("Field").push(c),
+		}
+	}
+	fields.into_iter().map(|it| it.trim().to_string()).collect()
+}
+
+/// Reads numbers like brokerages print them, with dollar signs and
+/// thousands separators.
+pub fn parse_number(text: &str) -> Option<f64> {
+	text.replace(['$', ','], "").parse::<f64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn split_line_unquotes_fields() {
+		let fields = super::split_line(" VTI ,\"Vanguard, \"\"Total\"\"\",,");
+		assert_eq!(vec!["VTI", "Vanguard, \"Total\"", "", ""], fields);
+	}
+
+	#[test]
+	fn parse_number_drops_dollars_and_separators() {
+		assert_eq!(Some(1201.5), super::parse_number("$1,201.50"));
+		assert_eq!(Some(-3.0), super::parse_number("-3"));
+		assert_eq!(None, super::parse_number("n/a"));
+	}
+}
@@ -1,11 +1,14 @@
-use chad_core::chad::Chad;
+use chrono::{Local, NaiveDate};
 use stringedit::Validity;
-use yui::{AfterFlow, ArcYard, Cling, Create, Flow, Pack, SenderLink, StringEdit, StringEditAction, yard};
+use yui::{AfterFlow, ArcYard, Cling, Confine, Create, Flow, Pack, SenderLink, StringEdit, StringEditAction, yard};
 use yui::palette::StrokeColor;
 use yui::yard::ButtonState;
 
-use crate::{OWNER, render};
-use crate::YardId::{LotAccountEdit, LotSharesEdit};
+use crate::render;
+use crate::vault::{LotBasis, Vault};
+use crate::YardId::{LotAccountEdit, LotAcquiredEdit, LotCostEdit, LotSharesEdit};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
 
 #[derive(Clone, Debug)]
 pub struct State {
@@ -13,24 +16,67 @@ pub struct State {
 	add_lot: bool,
 	account_edit: StringEdit,
 	shares_edit: StringEdit,
+	cost_per_share: bool,
+	cost_edit: StringEdit,
+	acquired_edit: StringEdit,
+	error: Option<String>,
 }
 
 impl State {
 	fn is_valid(&self) -> bool {
-		self.account_edit.is_valid() && self.shares_edit.is_valid()
+		self.account_edit.is_valid() && self.shares_edit.is_valid() && self.basis().is_ok()
+	}
+
+	fn shares(&self) -> Option<f64> {
+		self.shares_edit.chars.iter().cloned().collect::<String>().parse::<f64>().ok()
+	}
+
+	fn basis(&self) -> Result<LotBasis, &'static str> {
+		let cost_text = self.cost_edit.chars.iter().cloned().collect::<String>();
+		let acquired_text = self.acquired_edit.chars.iter().cloned().collect::<String>();
+		let per_share = if self.cost_per_share { Some(self.shares()) } else { None };
+		parse_basis(&cost_text, per_share, &acquired_text, Local::now().naive_local().date())
 	}
 }
 
+/// Cost basis and acquisition date are optional but must make sense
+/// when given. A cost per share comes with the shares it multiplies.
+fn parse_basis(cost_text: &str, per_share: Option<Option<f64>>, acquired_text: &str, today: NaiveDate) -> Result<LotBasis, &'static str> {
+	let cost = match cost_text.trim() {
+		"" => None,
+		text => match text.trim_start_matches('$').parse::<f64>() {
+			Ok(cost) if cost >= 0.0 => Some(cost),
+			_ => return Err("Cost must be a number of 0 or more"),
+		}
+	};
+	let cost = match (cost, per_share) {
+		(Some(cost), Some(shares)) => Some(cost * shares.ok_or("Cost per share needs shares")?),
+		(cost, _) => cost,
+	};
+	let acquired = match acquired_text.trim() {
+		"" => None,
+		text => match NaiveDate::parse_from_str(text, DATE_FORMAT) {
+			Ok(date) if date > today => return Err("Acquired cannot be in the future"),
+			Ok(date) => Some(date),
+			Err(_) => return Err("Acquired must be a date like 2020-01-31"),
+		}
+	};
+	Ok(LotBasis { cost, acquired })
+}
+
 pub enum Action {
 	Close,
 	Submit,
 	EditAccount(StringEditAction),
 	EditShares(StringEditAction),
+	ToggleCostPerShare,
+	EditCost(StringEditAction),
+	EditAcquired(StringEditAction),
 	Delete,
 }
 
 pub struct Spark {
-	pub chad: Chad,
+	pub vault: Vault,
 	pub squad_id: u64,
 	pub member_symbol: String,
 	pub lot_id: Option<u64>,
@@ -42,19 +88,26 @@ impl yui::Spark for Spark {
 	type Report = (u64, String, Option<u64>);
 
 	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
-		let (init_account, init_shares) = match self.lot_id {
-			None => ("".to_string(), "".to_string()),
+		let (init_account, init_shares, basis) = match self.lot_id {
+			None => ("".to_string(), "".to_string(), LotBasis::default()),
 			Some(lot_id) => {
-				let squad = self.chad.snap().squads(OWNER).into_iter().find(|it| it.id == self.squad_id).expect("Squad exists");
+				let squad = self.vault.squad(self.squad_id).expect("Squad exists");
 				let lot = squad.lots.into_iter().find(|it| it.id == lot_id).expect("Lot exists");
-				(lot.account.clone(), format!("{}", lot.shares))
+				let basis = self.vault.squad_annex(self.squad_id).lots.get(&lot_id).cloned().unwrap_or_default();
+				(lot.account.clone(), format!("{}", lot.shares), basis)
 			}
 		};
+		let init_cost = basis.cost.map(|it| format!("{}", it)).unwrap_or_default();
+		let init_acquired = basis.acquired.map(|it| it.format(DATE_FORMAT).to_string()).unwrap_or_default();
 		State {
 			symbol: self.member_symbol.to_owned(),
 			add_lot: self.lot_id.is_none(),
 			account_edit: StringEdit::new(init_account.clone(), init_account.len(), Validity::NotEmpty),
 			shares_edit: StringEdit::new(init_shares.clone(), init_shares.len(), Validity::Double),
+			cost_per_share: false,
+			cost_edit: StringEdit::new(init_cost.clone(), init_cost.len(), Validity::NotEmpty),
+			acquired_edit: StringEdit::new(init_acquired.clone(), init_acquired.len(), Validity::NotEmpty),
+			error: None,
 		}
 	}
 
@@ -66,31 +119,52 @@ impl yui::Spark for Spark {
 					let account = ctx.state().account_edit.chars.iter().cloned().collect::<String>().trim().to_owned();
 					let shares = ctx.state().shares_edit.chars.iter().cloned().collect::<String>().parse::<f64>().expect("Float in shares_edit");
 					let lot_id = self.lot_id.unwrap_or_else(rand::random);
-					self.chad.add_lot(self.squad_id, lot_id, &self.member_symbol, &account, shares);
-					let lot_path = (self.squad_id, self.member_symbol.to_owned(), Some(lot_id));
-					AfterFlow::Close(Some(lot_path))
+					let saved = self.vault.batch(|| {
+						self.vault.add_lot(self.squad_id, lot_id, &self.member_symbol, &account, shares)?;
+						self.vault.set_lot_basis(self.squad_id, lot_id, ctx.state().basis().expect("Valid basis"))
+					});
+					match saved {
+						Ok(()) => AfterFlow::Close(Some((self.squad_id, self.member_symbol.to_owned(), Some(lot_id)))),
+						Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+					}
 				} else {
 					AfterFlow::Close(None)
 				}
 			}
 			Action::EditAccount(action) => AfterFlow::Revise(State { account_edit: ctx.state().account_edit.edit(action), ..ctx.state().clone() }),
 			Action::EditShares(action) => AfterFlow::Revise(State { shares_edit: ctx.state().shares_edit.edit(action), ..ctx.state().clone() }),
+			Action::ToggleCostPerShare => AfterFlow::Revise(State { cost_per_share: !ctx.state().cost_per_share, ..ctx.state().clone() }),
+			Action::EditCost(action) => AfterFlow::Revise(State { cost_edit: ctx.state().cost_edit.edit(action), ..ctx.state().clone() }),
+			Action::EditAcquired(action) => AfterFlow::Revise(State { acquired_edit: ctx.state().acquired_edit.edit(action), ..ctx.state().clone() }),
 			Action::Delete => match self.lot_id {
 				None => AfterFlow::Ignore,
-				Some(lot_id) => {
-					self.chad.del_lot(self.squad_id, lot_id);
-					AfterFlow::Close(Some((self.squad_id, self.member_symbol.to_owned(), None)))
-				}
+				Some(lot_id) => match self.vault.del_lot(self.squad_id, lot_id) {
+					Ok(()) => AfterFlow::Close(Some((self.squad_id, self.member_symbol.to_owned(), None))),
+					Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+				},
 			},
 		}
 	}
 
 	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
-		let title = if state.add_lot { format!("Add Lot") } else { format!("Edit Lot") };
-		let content = yard::trellis(3, 1, Cling::Top, vec![
+		let title = if state.add_lot { "Add Lot" } else { "Edit Lot" };
+		let cost_label = if state.cost_per_share { "Cost per share" } else { "Total cost" };
+		let cost_toggle = if state.cost_per_share { "Enter total" } else { "Enter per share" };
+		let mut fields = vec![
 			yard::textfield(LotAccountEdit.as_i32(), "Account", state.account_edit.clone(), link.map(Action::EditAccount)),
 			yard::textfield(LotSharesEdit.as_i32(), "Shares", state.shares_edit.clone(), link.map(Action::EditShares)),
-		]).pack_top(2, yard::label(&state.symbol, StrokeColor::BodyOnBackground, Cling::LeftTop));
+			yard::textfield(LotCostEdit.as_i32(), cost_label, state.cost_edit.clone(), link.map(Action::EditCost))
+				.pack_right(20, yard::button(cost_toggle, ButtonState::enabled(link.map(|_| Action::ToggleCostPerShare))).confine_width(19, Cling::RightTop)),
+			yard::textfield(LotAcquiredEdit.as_i32(), "Acquired (YYYY-MM-DD)", state.acquired_edit.clone(), link.map(Action::EditAcquired)),
+		];
+		if let Err(problem) = state.basis() {
+			fields.push(yard::label(problem, StrokeColor::CommentOnBackground, Cling::Left));
+		}
+		if let Some(error) = &state.error {
+			fields.push(yard::label(error, StrokeColor::CommentOnBackground, Cling::Left));
+		}
+		let content = yard::trellis(3, 1, Cling::Top, fields)
+			.pack_top(2, yard::label(&state.symbol, StrokeColor::BodyOnBackground, Cling::LeftTop));
 		let submit_state = {
 			if state.is_valid() {
 				ButtonState::enabled(link.map(|_| Action::Submit))
@@ -99,7 +173,7 @@ impl yui::Spark for Spark {
 			}
 		};
 		let yard = render::dialog(
-			&title,
+			title,
 			link.map(|_| Action::Close),
 			submit_state,
 			if state.add_lot { None } else { Some(link.map(|_| Action::Delete)) },
@@ -109,3 +183,34 @@ impl yui::Spark for Spark {
 	}
 }
 
+#[cfg(test)]
+mod tests {
+	use chrono::NaiveDate;
+
+	use crate::vault::LotBasis;
+
+	fn today() -> NaiveDate {
+		NaiveDate::from_ymd_opt(2020, 6, 1).expect("Date")
+	}
+
+	#[test]
+	fn parse_basis_leaves_blank_fields_unset() {
+		assert_eq!(Ok(LotBasis::default()), super::parse_basis(" ", None, "", today()));
+		let acquired = NaiveDate::from_ymd_opt(2020, 1, 31);
+		assert_eq!(Ok(LotBasis { cost: Some(0.0), acquired }), super::parse_basis("$0", None, "2020-01-31", today()));
+	}
+
+	#[test]
+	fn parse_basis_multiplies_cost_per_share() {
+		assert_eq!(Ok(LotBasis { cost: Some(250.0), acquired: None }), super::parse_basis("25", Some(Some(10.0)), "", today()));
+		assert_eq!(Err("Cost per share needs shares"), super::parse_basis("25", Some(None), "", today()));
+		assert_eq!(Ok(LotBasis::default()), super::parse_basis("", Some(None), "", today()));
+	}
+
+	#[test]
+	fn parse_basis_rejects_bad_values() {
+		assert_eq!(Err("Cost must be a number of 0 or more"), super::parse_basis("-5", None, "", today()));
+		assert_eq!(Err("Acquired must be a date like 2020-01-31"), super::parse_basis("", None, "1/31/2020", today()));
+		assert_eq!(Err("Acquired cannot be in the future"), super::parse_basis("", None, "2020-06-02", today()));
+	}
+}
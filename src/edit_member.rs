@@ -1,17 +1,24 @@
 use std::collections::HashMap;
 
-use chad_core::chad::Chad;
+use chad_core::core::Squad;
 use stringedit::{StringEdit, Validity};
 use yui::{AfterFlow, ArcYard, Cling, Create, Flow, SenderLink, StringEditAction, yard};
+use yui::palette::StrokeColor;
 use yui::yard::ButtonState;
 
-use crate::{OWNER, render, YardId};
+use crate::{render, YardId};
+use crate::vault::{Band, Vault};
 
 #[derive(Clone, Debug)]
 pub(crate) struct State {
+	add_member: bool,
+	lot_count: usize,
 	symbol_edit: StringEdit,
 	price_edit: StringEdit,
+	absolute_edit: StringEdit,
+	relative_edit: StringEdit,
 	prices: HashMap<String, f64>,
+	error: Option<String>,
 }
 
 impl State {
@@ -21,37 +28,70 @@ impl State {
 	fn price(&self) -> f64 {
 		self.price_edit.chars.iter().clone().collect::<String>().parse::<f64>().unwrap_or(0.0)
 	}
+	fn is_valid(&self) -> bool {
+		self.symbol_edit.is_valid() && self.price_edit.is_valid() && render::band_from_edits(&self.absolute_edit, &self.relative_edit).is_some()
+	}
 }
 
 pub(crate) enum Action {
 	Close,
 	EditSymbol(StringEditAction),
 	EditPrice(StringEditAction),
+	EditAbsolute(StringEditAction),
+	EditRelative(StringEditAction),
 	Submit,
+	Delete,
 }
 
 pub(crate) struct Spark {
-	pub chad: Chad,
+	pub vault: Vault,
 	pub squad_id: u64,
+	pub member_symbol: Option<String>,
+}
+
+impl Spark {
+	fn squad(&self) -> Squad {
+		self.vault.squad(self.squad_id).expect("Squad exists")
+	}
 }
 
 impl yui::Spark for Spark {
 	type State = State;
 	type Action = Action;
-	type Report = (u64, String);
+	type Report = (u64, Option<String>);
 
 	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
-		let prices = {
-			let snap = self.chad.snap();
-			let squads = snap.squads(OWNER);
-			let squad_pos = squads.iter().position(|it| it.id == self.squad_id).expect("Squad exists");
-			let squad = &squads[squad_pos];
-			squad.prices.to_owned()
-		};
-		State {
-			symbol_edit: StringEdit::empty(Validity::NotEmpty),
-			price_edit: StringEdit::empty(Validity::Double),
-			prices,
+		let squad = self.squad();
+		let prices = squad.prices.to_owned();
+		match &self.member_symbol {
+			None => {
+				let (absolute_edit, relative_edit) = render::band_edits(&Band::default());
+				State {
+					add_member: true,
+					lot_count: 0,
+					symbol_edit: StringEdit::empty(Validity::NotEmpty),
+					price_edit: StringEdit::empty(Validity::Double),
+					absolute_edit,
+					relative_edit,
+					prices,
+					error: None,
+				}
+			}
+			Some(symbol) => {
+				let price = prices.get(symbol).map(|it| format!("{}", it)).unwrap_or_default();
+				let band = self.vault.squad_annex(squad.id).member_bands.get(symbol).cloned().unwrap_or_default();
+				let (absolute_edit, relative_edit) = render::band_edits(&band);
+				State {
+					add_member: false,
+					lot_count: squad.lots.iter().filter(|it| &it.symbol == symbol).count(),
+					symbol_edit: StringEdit::new(symbol.to_owned(), symbol.chars().count(), Validity::NotEmpty),
+					price_edit: StringEdit::new(price.to_owned(), price.chars().count(), Validity::Double),
+					absolute_edit,
+					relative_edit,
+					prices,
+					error: None,
+				}
+			}
 		}
 	}
 
@@ -59,11 +99,26 @@ impl yui::Spark for Spark {
 		match action {
 			Action::Submit => {
 				let state = ctx.state();
-				if state.price_edit.is_valid() && state.symbol_edit.is_valid() {
+				if state.is_valid() {
 					let symbol = state.symbol();
 					let price = state.price();
-					self.chad.add_member(self.squad_id, &symbol, price);
-					AfterFlow::Close(Some((self.squad_id, symbol)))
+					let band = render::band_from_edits(&state.absolute_edit, &state.relative_edit).expect("Valid band");
+					let squad = self.squad();
+					let renamed = self.member_symbol.as_ref().filter(|it| **it != symbol);
+					if renamed.is_some() && squad.members.iter().any(|it| it.symbol == symbol) {
+						return AfterFlow::Revise(State { error: Some(format!("{} is already a member", symbol)), ..state.clone() });
+					}
+					let saved = self.vault.batch(|| {
+						match &self.member_symbol {
+							Some(old_symbol) if old_symbol != &symbol => self.vault.rename_member(&squad, old_symbol, &symbol, price)?,
+							_ => self.vault.add_member(self.squad_id, &symbol, price)?,
+						}
+						self.vault.set_member_band(self.squad_id, &symbol, band)
+					});
+					match saved {
+						Ok(()) => AfterFlow::Close(Some((self.squad_id, Some(symbol)))),
+						Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..state.clone() }),
+					}
 				} else {
 					AfterFlow::Ignore
 				}
@@ -72,6 +127,7 @@ impl yui::Spark for Spark {
 			Action::EditSymbol(action) => {
 				let mut state = ctx.state().clone();
 				state.symbol_edit = state.symbol_edit.edit(action);
+				state.error = None;
 				let symbol = state.symbol();
 				if state.price_edit.chars.is_empty() && state.prices.contains_key(&symbol) {
 					let price = format!("{}", state.prices[&symbol]);
@@ -85,11 +141,28 @@ impl yui::Spark for Spark {
 				state.price_edit = state.price_edit.edit(action);
 				AfterFlow::Revise(state)
 			}
+			Action::EditAbsolute(action) => {
+				let mut state = ctx.state().clone();
+				state.absolute_edit = state.absolute_edit.edit(action);
+				AfterFlow::Revise(state)
+			}
+			Action::EditRelative(action) => {
+				let mut state = ctx.state().clone();
+				state.relative_edit = state.relative_edit.edit(action);
+				AfterFlow::Revise(state)
+			}
+			Action::Delete => match &self.member_symbol {
+				None => AfterFlow::Ignore,
+				Some(symbol) => match self.vault.del_member(&self.squad(), symbol) {
+					Ok(()) => AfterFlow::Close(Some((self.squad_id, None))),
+					Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+				},
+			},
 		}
 	}
 
 	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
-		let content = yard::trellis(3, 1, Cling::Top, vec![
+		let mut fields = vec![
 			yard::textfield(
 				YardId::MemberSymbolEdit.as_i32(),
 				"Symbol",
@@ -102,16 +175,26 @@ impl yui::Spark for Spark {
 				state.price_edit.clone(),
 				link.map(Action::EditPrice),
 			),
-		]);
+		];
+		fields.extend(render::band_fields(&state.absolute_edit, &state.relative_edit, link.map(Action::EditAbsolute), link.map(Action::EditRelative)));
+		fields.push(yard::label("Leave the band empty to use the squad band", StrokeColor::CommentOnBackground, Cling::Left));
+		if !state.add_member && state.lot_count > 0 {
+			let note = format!("Deleting also deletes {} lot(s)", state.lot_count);
+			fields.push(yard::label(note, StrokeColor::CommentOnBackground, Cling::Left));
+		}
+		if let Some(error) = &state.error {
+			fields.push(yard::label(error, StrokeColor::CommentOnBackground, Cling::Left));
+		}
+		let content = yard::trellis(3, 1, Cling::Top, fields);
 		let render = render::dialog(
-			"Add Member",
+			if state.add_member { "Add Member" } else { "Edit Member" },
 			link.map(|_| Action::Close),
-			if state.symbol_edit.is_valid() && state.price_edit.is_valid() {
+			if state.is_valid() {
 				ButtonState::enabled(link.map(|_| Action::Submit))
 			} else {
 				ButtonState::disabled()
 			},
-			None,
+			if state.add_member { None } else { Some(link.map(|_| Action::Delete)) },
 			content,
 		);
 		Some(render)
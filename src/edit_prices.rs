@@ -0,0 +1,121 @@
+use stringedit::{StringEdit, Validity};
+use yui::{AfterFlow, ArcYard, Cling, Confine, Create, Flow, Pack, SenderLink, StringEditAction, yard};
+use yui::palette::StrokeColor;
+use yui::yard::ButtonState;
+
+use crate::{import_prices, render, YardId};
+use crate::vault::Vault;
+
+#[derive(Clone, Debug)]
+pub struct State {
+	price_edits: Vec<(String, StringEdit)>,
+	error: Option<String>,
+}
+
+impl State {
+	fn is_valid(&self) -> bool {
+		self.price_edits.iter().all(|(_, edit)| edit.is_valid())
+	}
+}
+
+pub enum Action {
+	Close,
+	EditPrice(usize, StringEditAction),
+	Submit,
+	Import,
+	Imported,
+}
+
+pub struct Spark {
+	pub vault: Vault,
+	pub squad_id: u64,
+}
+
+impl Spark {
+	fn load(&self) -> State {
+		let squad = self.vault.squad(self.squad_id).expect("Squad exists");
+		let mut price_edits = squad.prices.iter()
+			.map(|(symbol, price)| {
+				let price = format!("{}", price);
+				let cursor_pos = price.chars().count();
+				(symbol.to_owned(), StringEdit::new(price, cursor_pos, Validity::Double))
+			})
+			.collect::<Vec<_>>();
+		price_edits.sort_by(|(a, _), (b, _)| a.cmp(b));
+		State { price_edits, error: None }
+	}
+}
+
+impl yui::Spark for Spark {
+	type State = State;
+	type Action = Action;
+	type Report = u64;
+
+	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
+		self.load()
+	}
+
+	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
+		match action {
+			Action::Close => AfterFlow::Close(None),
+			Action::EditPrice(index, action) => {
+				let mut state = ctx.state().clone();
+				let price_edit = state.price_edits[index].1.edit(action);
+				state.price_edits[index].1 = price_edit;
+				AfterFlow::Revise(state)
+			}
+			Action::Submit => if ctx.state().is_valid() {
+				let saved = self.vault.batch(|| {
+					for (symbol, edit) in &ctx.state().price_edits {
+						let price = edit.chars.iter().cloned().collect::<String>().parse::<f64>().expect("Float in price_edit");
+						self.vault.set_price(self.squad_id, symbol, price)?;
+					}
+					Ok(())
+				});
+				match saved {
+					Ok(()) => AfterFlow::Close(Some(self.squad_id)),
+					Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+				}
+			} else {
+				AfterFlow::Ignore
+			},
+			Action::Import => {
+				let spark = import_prices::Spark { vault: self.vault.clone() };
+				ctx.start_prequel(spark, ctx.link().map(|_| Action::Imported));
+				AfterFlow::Ignore
+			}
+			Action::Imported => AfterFlow::Revise(self.load()),
+		}
+	}
+
+	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let content = if state.price_edits.is_empty() {
+			yard::label("No prices", StrokeColor::CommentOnBackground, Cling::Center)
+		} else {
+			let items = state.price_edits.iter().enumerate().map(|(index, (symbol, edit))| {
+				let field = yard::textfield(
+					YardId::PriceEdit(index).as_i32(),
+					symbol,
+					edit.clone(),
+					link.map(move |action| Action::EditPrice(index, action)),
+				);
+				(3, field)
+			}).collect();
+			yard::list(YardId::PricesList.as_i32(), 0, items)
+		};
+		let import = yard::button("Import quotes.csv", ButtonState::enabled(link.map(|_| Action::Import)));
+		let content = content.pack_bottom(4, import.confine(23, 3, Cling::Bottom));
+		let content = match &state.error {
+			None => content,
+			Some(error) => content.pack_bottom(1, yard::label(error, StrokeColor::CommentOnBackground, Cling::Left)),
+		};
+		let yard = render::dialog(
+			"Prices",
+			link.map(|_| Action::Close),
+			if state.is_valid() { ButtonState::enabled(link.map(|_| Action::Submit)) } else { ButtonState::disabled() },
+			None,
+			content,
+		);
+		Some(yard)
+	}
+}
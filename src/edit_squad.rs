@@ -1,64 +1,196 @@
-use chad_core::chad::Chad;
+use chad_core::core::Squad;
 use stringedit::{StringEdit, Validity};
-use yui::{AfterFlow, ArcYard, Create, Flow, SenderLink, Spark, StringEditAction, yard};
+use yui::{AfterFlow, ArcYard, Cling, Create, Flow, SenderLink, Spark, StringEditAction, yard};
+use yui::palette::StrokeColor;
 use yui::yard::ButtonState;
 
 use crate::{render, YardId};
+use crate::vault::{Band, Vault};
+
+#[derive(Copy, Clone, Debug)]
+pub enum Mode {
+	Add,
+	Rename(u64),
+	Duplicate(u64),
+}
 
 pub struct EditSquadSpark {
-	pub(crate) chad: Chad,
-	pub(crate) owner: u64,
+	pub(crate) vault: Vault,
+	pub(crate) mode: Mode,
+}
+
+impl EditSquadSpark {
+	fn squad(&self, squad_id: u64) -> Squad {
+		self.vault.squad(squad_id).expect("Squad exists")
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct State {
+	mode: Mode,
+	name_edit: StringEdit,
+	absolute_edit: StringEdit,
+	relative_edit: StringEdit,
+	confirm_delete: Option<(String, usize, usize)>,
+	error: Option<String>,
+}
+
+impl State {
+	fn name(&self) -> String {
+		self.name_edit.chars.iter().cloned().collect::<String>().trim().to_owned()
+	}
+
+	fn is_valid(&self) -> bool {
+		self.name_edit.is_valid() && render::band_from_edits(&self.absolute_edit, &self.relative_edit).is_some()
+	}
 }
 
+#[allow(clippy::enum_variant_names)]
 pub enum Action {
 	Close,
 	NameAction(StringEditAction),
+	EditAbsolute(StringEditAction),
+	EditRelative(StringEditAction),
 	Submit,
+	Delete,
+	CancelDelete,
+	ConfirmDelete,
 }
 
 impl Spark for EditSquadSpark {
-	type State = StringEdit;
+	type State = State;
 	type Action = Action;
 	type Report = u64;
 
 	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
-		StringEdit::new("", 0, Validity::NotEmpty)
+		let name = match self.mode {
+			Mode::Add => String::new(),
+			Mode::Rename(squad_id) => self.squad(squad_id).name,
+			Mode::Duplicate(squad_id) => format!("{} Copy", self.squad(squad_id).name),
+		};
+		let band = match self.mode {
+			Mode::Add => Band::default(),
+			Mode::Rename(squad_id) | Mode::Duplicate(squad_id) => self.vault.squad_annex(squad_id).band,
+		};
+		let (absolute_edit, relative_edit) = render::band_edits(&band);
+		let cursor_pos = name.chars().count();
+		State {
+			mode: self.mode,
+			name_edit: StringEdit::new(name, cursor_pos, Validity::NotEmpty),
+			absolute_edit,
+			relative_edit,
+			confirm_delete: None,
+			error: None,
+		}
 	}
 
 	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
 		match action {
 			Action::Close => AfterFlow::Close(None),
 			Action::NameAction(action) => {
-				let name_edit = ctx.state().edit(action);
-				AfterFlow::Revise(name_edit)
+				let mut state = ctx.state().clone();
+				state.name_edit = state.name_edit.edit(action);
+				AfterFlow::Revise(state)
+			}
+			Action::EditAbsolute(action) => {
+				let mut state = ctx.state().clone();
+				state.absolute_edit = state.absolute_edit.edit(action);
+				AfterFlow::Revise(state)
+			}
+			Action::EditRelative(action) => {
+				let mut state = ctx.state().clone();
+				state.relative_edit = state.relative_edit.edit(action);
+				AfterFlow::Revise(state)
 			}
 			Action::Submit => if ctx.state().is_valid() {
-				let id = rand::random();
-				self.chad.add_squad(id, &ctx.state().chars.iter().cloned().collect::<String>().trim(), self.owner);
-				AfterFlow::Close(Some(id))
+				let name = ctx.state().name();
+				let band = render::band_from_edits(&ctx.state().absolute_edit, &ctx.state().relative_edit).expect("Valid band");
+				let saved = self.vault.batch(|| {
+					let id = match self.mode {
+						Mode::Add => {
+							let id = rand::random();
+							self.vault.add_squad(id, &name)?;
+							id
+						}
+						Mode::Rename(squad_id) => {
+							self.vault.rename_squad(squad_id, &name)?;
+							squad_id
+						}
+						Mode::Duplicate(squad_id) => {
+							let id = rand::random();
+							self.vault.duplicate_squad(&self.squad(squad_id), id, &name)?;
+							id
+						}
+					};
+					self.vault.set_band(id, band)?;
+					Ok(id)
+				});
+				match saved {
+					Ok(id) => AfterFlow::Close(Some(id)),
+					Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+				}
 			} else {
 				AfterFlow::Ignore
 			},
+			Action::Delete => match self.mode {
+				Mode::Rename(squad_id) => {
+					let squad = self.squad(squad_id);
+					let mut state = ctx.state().clone();
+					state.confirm_delete = Some((squad.name.to_owned(), squad.members.len(), squad.lots.len()));
+					AfterFlow::Revise(state)
+				}
+				_ => AfterFlow::Ignore,
+			},
+			Action::CancelDelete => {
+				let mut state = ctx.state().clone();
+				state.confirm_delete = None;
+				AfterFlow::Revise(state)
+			}
+			Action::ConfirmDelete => match self.mode {
+				Mode::Rename(squad_id) => match self.vault.del_squad(squad_id) {
+					Ok(()) => AfterFlow::Close(Some(squad_id)),
+					Err(error) => AfterFlow::Revise(State { confirm_delete: None, error: Some(render::save_error(error)), ..ctx.state().clone() }),
+				},
+				_ => AfterFlow::Ignore,
+			},
 		}
 	}
 
 	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
-		let trellis = yard::list(
-			YardId::EditSquadList.as_i32(),
-			0,
-			vec![(3, yard::textfield(YardId::NameField.as_i32(), "Name", state.clone(), link.map(Action::NameAction))), ],
-		);
+		if let Some((name, member_count, lot_count)) = &state.confirm_delete {
+			let text = format!("Delete {} with {} member(s) and {} lot(s)?", name, member_count, lot_count);
+			let render = render::dialog(
+				"Delete Squad",
+				link.map(|_| Action::CancelDelete),
+				ButtonState::enabled(link.map(|_| Action::ConfirmDelete)),
+				None,
+				yard::label(text, StrokeColor::BodyOnBackground, Cling::LeftTop),
+			);
+			return Some(render);
+		}
+		let mut fields = vec![(3, yard::textfield(YardId::NameField.as_i32(), "Name", state.name_edit.clone(), link.map(Action::NameAction)))];
+		let band_fields = render::band_fields(&state.absolute_edit, &state.relative_edit, link.map(Action::EditAbsolute), link.map(Action::EditRelative));
+		fields.extend(band_fields.into_iter().map(|it| (3, it)));
+		if let Some(error) = &state.error {
+			fields.push((1, yard::label(error, StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		let trellis = yard::list(YardId::EditSquadList.as_i32(), 0, fields);
+		let (title, delete_link) = match state.mode {
+			Mode::Add => ("Add Squad", None),
+			Mode::Rename(_) => ("Edit Squad", Some(link.map(|_| Action::Delete))),
+			Mode::Duplicate(_) => ("Duplicate Squad", None),
+		};
 		let render = render::dialog(
-			"Add Squad",
+			title,
 			link.map(|_| Action::Close),
 			if state.is_valid() {
 				ButtonState::enabled(link.map(|_| Action::Submit))
 			} else {
 				ButtonState::disabled()
 			},
-			None,
+			delete_link,
 			trellis,
 		);
 		Some(render)
 	}
-}
\ No newline at end of file
+}
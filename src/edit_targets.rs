@@ -0,0 +1,184 @@
+use chad_core::core::Squad;
+use stringedit::{StringEdit, Validity};
+use yui::{AfterFlow, ArcYard, Cling, Confine, Create, Flow, SenderLink, StringEditAction, yard};
+use yui::palette::StrokeColor;
+use yui::yard::ButtonState;
+
+use crate::{compute, render, sprint, YardId};
+use crate::vault::{SquadAnnex, Vault, Weighting};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Kind { Rank, Curve, Percent }
+
+#[derive(Clone, Debug)]
+pub struct State {
+	squad: Squad,
+	annex: SquadAnnex,
+	kind: Kind,
+	ratio_edit: StringEdit,
+	percent_edits: Vec<(String, StringEdit)>,
+	error: Option<String>,
+}
+
+impl State {
+	fn percent_sum(&self) -> f64 {
+		self.percent_edits.iter().map(|(_, edit)| parse(edit).unwrap_or(0.0)).sum()
+	}
+
+	/// The weighting in the edits or None while they are not valid.
+	fn weighting(&self) -> Option<Weighting> {
+		match self.kind {
+			Kind::Rank => Some(Weighting::Rank),
+			Kind::Curve => parse(&self.ratio_edit).filter(|it| *it > 0.0).map(Weighting::Curve),
+			Kind::Percent => {
+				let percents = self.percent_edits.iter()
+					.map(|(symbol, edit)| parse(edit).filter(|it| *it >= 0.0).map(|it| (symbol.to_owned(), it)))
+					.collect::<Option<_>>()?;
+				if (self.percent_sum() - 100.0).abs() < 0.01 { Some(Weighting::Percent(percents)) } else { None }
+			}
+		}
+	}
+}
+
+fn parse(edit: &StringEdit) -> Option<f64> {
+	if edit.is_valid() {
+		edit.chars.iter().cloned().collect::<String>().parse::<f64>().ok()
+	} else {
+		None
+	}
+}
+
+fn number_edit(value: f64) -> StringEdit {
+	let text = format!("{}", value);
+	let cursor_pos = text.chars().count();
+	StringEdit::new(text, cursor_pos, Validity::Double)
+}
+
+pub enum Action {
+	Close,
+	NextKind,
+	EditRatio(StringEditAction),
+	EditPercent(usize, StringEditAction),
+	Submit,
+}
+
+pub struct Spark {
+	pub vault: Vault,
+	pub squad_id: u64,
+}
+
+impl yui::Spark for Spark {
+	type State = State;
+	type Action = Action;
+	type Report = u64;
+
+	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
+		let squad = self.vault.squad(self.squad_id).expect("Squad exists");
+		let annex = self.vault.squad_annex(squad.id);
+		let (kind, ratio) = match annex.weighting {
+			Weighting::Rank => (Kind::Rank, 0.5),
+			Weighting::Curve(ratio) => (Kind::Curve, ratio),
+			Weighting::Percent(_) => (Kind::Percent, 0.5),
+		};
+		let drifts = compute::drifts(&squad, &annex);
+		let portions = drifts.iter().map(|it| it.target_portion).collect::<Vec<_>>();
+		let percent_edits = drifts.iter().zip(compute::rounded_percents(&portions))
+			.map(|(drift, percent)| (drift.symbol().to_string(), number_edit(percent)))
+			.collect();
+		State { squad, annex, kind, ratio_edit: number_edit(ratio), percent_edits, error: None }
+	}
+
+	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
+		match action {
+			Action::Close => AfterFlow::Close(None),
+			Action::NextKind => {
+				let mut state = ctx.state().clone();
+				state.kind = match state.kind {
+					Kind::Rank => Kind::Curve,
+					Kind::Curve => Kind::Percent,
+					Kind::Percent => Kind::Rank,
+				};
+				AfterFlow::Revise(state)
+			}
+			Action::EditRatio(action) => {
+				let mut state = ctx.state().clone();
+				state.ratio_edit = state.ratio_edit.edit(action);
+				AfterFlow::Revise(state)
+			}
+			Action::EditPercent(index, action) => {
+				let mut state = ctx.state().clone();
+				let percent_edit = state.percent_edits[index].1.edit(action);
+				state.percent_edits[index].1 = percent_edit;
+				AfterFlow::Revise(state)
+			}
+			Action::Submit => match ctx.state().weighting() {
+				None => AfterFlow::Ignore,
+				Some(weighting) => match self.vault.set_weighting(self.squad_id, weighting) {
+					Ok(()) => AfterFlow::Close(Some(self.squad_id)),
+					Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+				},
+			},
+		}
+	}
+
+	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let kind_text = match state.kind {
+			Kind::Rank => "Weighting: Rank",
+			Kind::Curve => "Weighting: Curve",
+			Kind::Percent => "Weighting: Percent",
+		};
+		let kind_button = yard::button(kind_text, ButtonState::enabled(link.map(|_| Action::NextKind)));
+		let mut items = vec![(3, kind_button.confine_width(24, Cling::Left))];
+		match state.kind {
+			Kind::Rank => {
+				items.push((1, yard::label("Targets follow each member's rank", StrokeColor::CommentOnBackground, Cling::Left)));
+			}
+			Kind::Curve => {
+				let field = yard::textfield(YardId::TargetRatioEdit.as_i32(), "Ratio", state.ratio_edit.clone(), link.map(Action::EditRatio));
+				items.push((3, field));
+				items.push((1, yard::label("Each rank weighs the ratio times the rank above it", StrokeColor::CommentOnBackground, Cling::Left)));
+			}
+			Kind::Percent => {
+				for (index, (symbol, edit)) in state.percent_edits.iter().enumerate() {
+					let field = yard::textfield(
+						YardId::TargetEdit(index).as_i32(),
+						symbol,
+						edit.clone(),
+						link.map(move |action| Action::EditPercent(index, action)),
+					);
+					items.push((3, field));
+				}
+				let text = format!("Total {}% of 100%", sprint::amount_prefix(state.percent_sum(), ""));
+				items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+				if state.percent_edits.iter().any(|(_, edit)| parse(edit).map(|it| it < 0.0) == Some(true)) {
+					items.push((1, yard::label("Percents cannot be negative", StrokeColor::CommentOnBackground, Cling::Left)));
+				}
+			}
+		}
+		if let Some(error) = &state.error {
+			items.push((1, yard::label(error, StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		let weighting = state.weighting();
+		if let Some(weighting) = &weighting {
+			let annex = SquadAnnex { weighting: weighting.to_owned(), ..state.annex.clone() };
+			items.push((1, yard::empty()));
+			for drift in compute::drifts(&state.squad, &annex) {
+				let text = format!(
+					"{} {}% {}",
+					drift.symbol(),
+					sprint::amount_prefix(drift.target_portion * 100.0, ""),
+					sprint::amount(drift.target_value),
+				);
+				items.push((1, yard::label(text, StrokeColor::BodyOnBackground, Cling::Left)));
+			}
+		}
+		let yard = render::dialog(
+			"Targets",
+			link.map(|_| Action::Close),
+			if weighting.is_some() { ButtonState::enabled(link.map(|_| Action::Submit)) } else { ButtonState::disabled() },
+			None,
+			yard::list(YardId::TargetsList.as_i32(), 0, items),
+		);
+		Some(yard)
+	}
+}
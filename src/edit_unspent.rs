@@ -1,28 +1,38 @@
-use chad_core::chad::Chad;
+use std::collections::{BTreeMap, BTreeSet};
+
 use stringedit::Validity;
-use yui::{AfterFlow, ArcYard, Cling, Confine, Create, Flow, SenderLink, StringEdit, StringEditAction, yard};
+use yui::{AfterFlow, ArcYard, Cling, Create, Flow, SenderLink, StringEdit, StringEditAction, yard};
+use yui::palette::StrokeColor;
 use yui::yard::ButtonState;
 
-use crate::render;
-use crate::YardId::UnspentEdit;
+use crate::{compute, render, YardId};
+use crate::vault::Vault;
 
 #[derive(Debug, Clone)]
 pub struct State {
-	pub string_edit: StringEdit
+	/// One edit per account. A squad without lots has a single edit for
+	/// the unnamed account.
+	pub cash_edits: Vec<(String, StringEdit)>,
+	pub error: Option<String>,
+}
+
+impl State {
+	fn is_valid(&self) -> bool {
+		self.cash_edits.iter().all(|(_, edit)| edit.is_valid())
+	}
 }
 
 #[derive(Debug)]
 pub enum Action {
 	Close,
-	Edit(StringEditAction),
+	Edit(usize, StringEditAction),
 	Submit,
 }
 
 #[derive(Debug)]
 pub struct Spark {
-	pub chad: Chad,
+	pub vault: Vault,
 	pub squad_id: u64,
-	pub unspent: Option<f64>,
 }
 
 impl yui::Spark for Spark {
@@ -31,11 +41,19 @@ impl yui::Spark for Spark {
 	type Report = (u64, f64);
 
 	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
-		let string = match self.unspent {
-			None => String::new(),
-			Some(unspent) => format!("{}", unspent),
-		};
-		State { string_edit: StringEdit::new(string, 0, Validity::Double) }
+		let squad = self.vault.squad(self.squad_id).expect("Squad exists");
+		let cash = compute::squad_cash(&squad, &self.vault.squad_annex(squad.id));
+		let mut accounts = squad.lots.iter().map(|it| it.account.to_owned()).collect::<BTreeSet<_>>();
+		accounts.extend(cash.keys().filter(|it| !it.is_empty()).cloned());
+		if accounts.is_empty() {
+			accounts.insert(String::new());
+		}
+		let cash_edits = accounts.into_iter().map(|account| {
+			let amount = format!("{}", cash.get(&account).cloned().unwrap_or(0.0));
+			let cursor_pos = amount.chars().count();
+			(account, StringEdit::new(amount, cursor_pos, Validity::Double))
+		}).collect();
+		State { cash_edits, error: None }
 	}
 
 	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
@@ -43,26 +61,54 @@ impl yui::Spark for Spark {
 			Action::Close => {
 				AfterFlow::Close(None)
 			}
-			Action::Edit(action) => {
-				AfterFlow::Revise(State { string_edit: ctx.state().string_edit.edit(action) })
-			}
-			Action::Submit => {
-				let string = ctx.state().string_edit.chars.iter().cloned().collect::<String>();
-				let unspent = string.parse::<f64>().expect("parse f64");
-				self.chad.set_unspent(self.squad_id, unspent);
-				AfterFlow::Close(Some((self.squad_id, unspent)))
+			Action::Edit(index, action) => {
+				let mut state = ctx.state().clone();
+				let cash_edit = state.cash_edits[index].1.edit(action);
+				state.cash_edits[index].1 = cash_edit;
+				AfterFlow::Revise(state)
 			}
+			Action::Submit => if ctx.state().is_valid() {
+				let cash = ctx.state().cash_edits.iter().map(|(account, edit)| {
+					let amount = edit.chars.iter().cloned().collect::<String>().parse::<f64>().expect("parse f64");
+					(account.to_owned(), amount)
+				}).collect::<BTreeMap<_, _>>();
+				let unspent = cash.values().sum();
+				let saved = if cash.keys().all(String::is_empty) {
+					self.vault.set_unspent(self.squad_id, unspent)
+				} else {
+					self.vault.set_account_cash(self.squad_id, cash)
+				};
+				match saved {
+					Ok(()) => AfterFlow::Close(Some((self.squad_id, unspent))),
+					Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+				}
+			} else {
+				AfterFlow::Ignore
+			},
 		}
 	}
 
 	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let mut items = state.cash_edits.iter().enumerate().map(|(index, (account, edit))| {
+			let label = if account.is_empty() { "Unspent" } else { account.as_str() };
+			let field = yard::textfield(
+				YardId::UnspentEdit(index).as_i32(),
+				label,
+				edit.clone(),
+				link.map(move |action| Action::Edit(index, action)),
+			);
+			(3, field)
+		}).collect::<Vec<_>>();
+		if let Some(error) = &state.error {
+			items.push((1, yard::label(error, StrokeColor::CommentOnBackground, Cling::Left)));
+		}
 		let yard = render::dialog(
 			"Set Unspent",
 			link.map(|_| Action::Close),
-			if state.string_edit.is_valid() { ButtonState::enabled(link.map(|_| Action::Submit)) } else { ButtonState::disabled() },
+			if state.is_valid() { ButtonState::enabled(link.map(|_| Action::Submit)) } else { ButtonState::disabled() },
 			None,
-			yard::textfield(UnspentEdit.as_i32(), "Unspent", state.string_edit.clone(), link.map(Action::Edit)).confine_height(3, Cling::Top),
+			yard::list(YardId::UnspentList.as_i32(), 0, items),
 		);
 		Some(yard)
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chad_core::core::Squad;
+use serde_json::{json, Value};
+
+use crate::compute;
+use crate::vault::SquadAnnex;
+
+/// Drift of every member in a squad along with the squad totals.
+pub fn drift_json(squad: &Squad, annex: &SquadAnnex) -> Value {
+	let reports = compute::drifts(squad, annex);
+	let members = reports.iter().map(|report| json!({
+		"symbol": report.symbol(),
+		"rank": report.rank,
+		"target_portion": report.target_portion,
+		"market_value": report.market_value,
+		"target_value": report.target_value,
+		"drift_amount": report.drift_amount(),
+		"drift_shares": report.drift_shares(),
+		"out_of_band": report.out_of_band,
+	})).collect::<Vec<_>>();
+	let market_value = reports.iter().map(|it| it.market_value).sum::<f64>();
+	json!({
+		"squad_id": squad.id,
+		"name": squad.name,
+		"unspent": squad.unspent,
+		"market_value": market_value,
+		"total_value": market_value + squad.unspent,
+		"members": members,
+	})
+}
+
+/// Members, prices, targets, lots and unspent cash of a squad.
+pub fn squad_json(squad: &Squad, annex: &SquadAnnex) -> Value {
+	let members = compute::drifts(squad, annex).iter().map(|drift| json!({
+		"symbol": drift.symbol(),
+		"rank": drift.rank,
+		"price": drift.price,
+		"target_portion": drift.target_portion,
+		"target_value": drift.target_value,
+		"market_value": drift.market_value,
+	})).collect::<Vec<_>>();
+	let lots = squad.lots.iter().map(|lot| {
+		let basis = annex.lots.get(&lot.id).cloned().unwrap_or_default();
+		json!({
+			"lot_id": lot.id,
+			"symbol": lot.symbol,
+			"account": lot.account,
+			"shares": lot.shares,
+			"cost": basis.cost,
+			"acquired": basis.acquired,
+		})
+	}).collect::<Vec<_>>();
+	json!({
+		"squad_id": squad.id,
+		"name": squad.name,
+		"unspent": squad.unspent,
+		"cash": compute::squad_cash(squad, annex),
+		"weighting": annex.weighting,
+		"members": members,
+		"lots": lots,
+	})
+}
+
+pub const SQUAD_CSV_HEADER: &str = "record,symbol,account,shares,price,target_portion,amount,cost,acquired";
+
+/// A squad as csv rows in one table. The record column tells member, lot
+/// and cash rows apart and columns that do not apply to a row stay empty.
+pub fn squad_csv(squad: &Squad, annex: &SquadAnnex) -> String {
+	let mut rows = vec![SQUAD_CSV_HEADER.to_string()];
+	for drift in compute::drifts(squad, annex) {
+		rows.push(csv_row(&["member", drift.symbol(), "", "", &drift.price.to_string(), &drift.target_portion.to_string(), &drift.target_value.to_string(), "", ""]));
+	}
+	for lot in &squad.lots {
+		let basis = annex.lots.get(&lot.id).cloned().unwrap_or_default();
+		let cost = basis.cost.map(|it| it.to_string()).unwrap_or_default();
+		let acquired = basis.acquired.map(|it| it.format("%Y-%m-%d").to_string()).unwrap_or_default();
+		rows.push(csv_row(&["lot", &lot.symbol, &lot.account, &lot.shares.to_string(), "", "", "", &cost, &acquired]));
+	}
+	for (account, cash) in compute::squad_cash(squad, annex) {
+		rows.push(csv_row(&["cash", "", &account, "", "", "", &cash.to_string(), "", ""]));
+	}
+	rows.push(String::new());
+	rows.join("\n")
+}
+
+/// Folder under the data directory that receives squad exports.
+pub const EXPORTS_DIR: &str = "exports";
+
+/// Writes a squad as csv and json files named after the squad and
+/// returns their paths.
+pub fn write_squad(dir: &Path, squad: &Squad, annex: &SquadAnnex) -> io::Result<(PathBuf, PathBuf)> {
+	fs::create_dir_all(dir)?;
+	let stem = file_stem(&squad.name);
+	let csv_path = dir.join(format!("{}.csv", stem));
+	let json_path = dir.join(format!("{}.json", stem));
+	fs::write(&csv_path, squad_csv(squad, annex))?;
+	let json = serde_json::to_string_pretty(&squad_json(squad, annex)).expect("Squad to json");
+	fs::write(&json_path, json)?;
+	Ok((csv_path, json_path))
+}
+
+fn file_stem(name: &str) -> String {
+	name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect()
+}
+
+fn csv_row(fields: &[&str]) -> String {
+	fields.iter().map(|it| csv_field(it)).collect::<Vec<_>>().join(",")
+}
+
+/// Quotes a field when it holds a comma, quote or line break.
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn csv_field_quotes_only_when_needed() {
+		assert_eq!("Roth IRA", super::csv_field("Roth IRA"));
+		assert_eq!("\"Joint, taxable\"", super::csv_field("Joint, taxable"));
+		assert_eq!("\"The \"\"big\"\" one\"", super::csv_field("The \"big\" one"));
+	}
+
+	#[test]
+	fn file_stem_replaces_path_characters() {
+		assert_eq!("Kids-college_2030", super::file_stem("Kids/college_2030"));
+	}
+}
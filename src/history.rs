@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use chad_core::core::{Lot, Squad};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::sprint;
+use crate::compute::Side;
+use crate::vault::{Band, LotBasis, SquadAnnex, Trade, Weighting};
+
+/// Changes live apart from the annex, one json change per line, so saving
+/// a change appends a line instead of rewriting every change before it.
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// A squad value as it stood before or after a change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Record {
+	Squad { name: String, owner: u64 },
+	Member { symbol: String, price: f64 },
+	Lot { lot_id: u64, symbol: String, account: String, shares: f64, basis: LotBasis },
+	Unspent { amount: f64, accounts: BTreeMap<String, f64> },
+	Weighting(Weighting),
+	Order(Vec<String>),
+	/// A squad band or, with a symbol, a member band.
+	Band { symbol: Option<String>, band: Band },
+	Trade(Trade),
+}
+
+impl Record {
+	pub fn subject(&self) -> String {
+		match self {
+			Record::Squad { .. } => "Squad".to_string(),
+			Record::Member { symbol, .. } => format!("Member {}", symbol),
+			Record::Lot { lot_id, symbol, .. } => format!("Lot {} #{}", symbol, lot_id % 10000),
+			Record::Unspent { .. } => "Unspent".to_string(),
+			Record::Weighting(_) => "Targets".to_string(),
+			Record::Order(_) => "Rank order".to_string(),
+			Record::Band { symbol: None, .. } => "Band".to_string(),
+			Record::Band { symbol: Some(symbol), .. } => format!("Band of {}", symbol),
+			Record::Trade(trade) => format!("Trade {}", trade.symbol),
+		}
+	}
+
+	pub fn value(&self) -> String {
+		match self {
+			Record::Squad { name, .. } => name.to_owned(),
+			Record::Member { price, .. } => format!("price {}", sprint::amount(*price)),
+			Record::Lot { account, shares, basis, .. } => {
+				let mut value = format!("{} sh in {}", shares, account);
+				if let Some(cost) = basis.cost {
+					value.push_str(&format!(", cost {}", sprint::amount(cost)));
+				}
+				if let Some(acquired) = basis.acquired {
+					value.push_str(&format!(", acquired {}", acquired.format("%Y-%m-%d")));
+				}
+				value
+			}
+			Record::Unspent { amount, accounts } => {
+				let split = accounts.iter()
+					.map(|(account, cash)| format!("{} {}", account, sprint::amount(*cash)))
+					.collect::<Vec<_>>();
+				if split.is_empty() {
+					sprint::amount(*amount)
+				} else {
+					format!("{} ({})", sprint::amount(*amount), split.join(", "))
+				}
+			}
+			Record::Weighting(Weighting::Rank) => "by rank".to_string(),
+			Record::Weighting(Weighting::Curve(ratio)) => format!("curve {}", ratio),
+			Record::Weighting(Weighting::Percent(percents)) => percents.iter()
+				.map(|(symbol, percent)| format!("{} {}%", symbol, percent))
+				.collect::<Vec<_>>()
+				.join(", "),
+			Record::Order(order) => order.join(", "),
+			Record::Band { band, .. } => {
+				let limit = |limit: Option<f64>, unit: &str| limit.map(|it| format!("{}{}", it, unit)).unwrap_or_else(|| "-".to_string());
+				format!("{} of total, {} of target", limit(band.absolute, " pts"), limit(band.relative, "%"))
+			}
+			Record::Trade(trade) => {
+				let side = match trade.side {
+					Side::Buy => "buy",
+					Side::Sell => "sell",
+				};
+				format!("{} {} sh in {} @ {} on {}", side, trade.shares, trade.account, sprint::amount(trade.price), trade.date.format("%Y-%m-%d"))
+			}
+		}
+	}
+
+	/// The same lot with a new cost basis. None for records other than lots.
+	pub fn with_basis(&self, basis: LotBasis) -> Option<Record> {
+		match self {
+			Record::Lot { lot_id, symbol, account, shares, .. } => {
+				Some(Record::Lot { lot_id: *lot_id, symbol: symbol.to_owned(), account: account.to_owned(), shares: *shares, basis })
+			}
+			_ => None,
+		}
+	}
+}
+
+pub fn member_record(squad: &Squad, symbol: &str) -> Option<Record> {
+	squad.members.iter().find(|it| it.symbol == symbol)?;
+	let price = squad.prices.get(symbol).cloned().unwrap_or(0.0);
+	Some(Record::Member { symbol: symbol.to_string(), price })
+}
+
+pub fn lot_record(lots: &[Lot], annex: &SquadAnnex, lot_id: u64) -> Option<Record> {
+	let lot = lots.iter().find(|it| it.id == lot_id)?;
+	let basis = annex.lots.get(&lot_id).cloned().unwrap_or_default();
+	Some(Record::Lot { lot_id, symbol: lot.symbol.to_owned(), account: lot.account.to_owned(), shares: lot.shares, basis })
+}
+
+pub fn unspent_record(unspent: f64, annex: &SquadAnnex) -> Record {
+	Record::Unspent { amount: unspent, accounts: annex.cash.to_owned() }
+}
+
+/// One change to a squad along with the value it replaced.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+	pub squad_id: u64,
+	/// Changes made together share a batch.
+	pub batch: u64,
+	pub at: NaiveDateTime,
+	pub who: String,
+	pub before: Option<Record>,
+	pub after: Option<Record>,
+}
+
+impl Change {
+	pub fn describe(&self) -> String {
+		match (&self.before, &self.after) {
+			(None, None) => "Nothing".to_string(),
+			(None, Some(after)) => format!("{} added: {}", after.subject(), after.value()),
+			(Some(before), None) => format!("{} removed: {}", before.subject(), before.value()),
+			(Some(before), Some(after)) => format!("{}: {} -> {}", after.subject(), before.value(), after.value()),
+		}
+	}
+}
+
+/// Batches that can be undone and redone, most recent last.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UndoStacks {
+	#[serde(default)]
+	pub undo: Vec<u64>,
+	#[serde(default)]
+	pub redo: Vec<u64>,
+}
+
+impl UndoStacks {
+	/// Adds the batch of a new edit. New edits leave nothing to redo.
+	pub fn push(&mut self, batch: u64) {
+		if self.undo.last() != Some(&batch) {
+			self.undo.push(batch);
+			self.redo.clear();
+		}
+	}
+
+	pub fn undone(&mut self) {
+		if let Some(batch) = self.undo.pop() {
+			self.redo.push(batch);
+		}
+	}
+
+	pub fn redone(&mut self) {
+		if let Some(batch) = self.redo.pop() {
+			self.undo.push(batch);
+		}
+	}
+}
+
+/// A squad value to move from one record to another.
+pub type Move = (u64, Option<Record>, Option<Record>);
+
+/// The moves that take back a batch, latest change first. Changes keep the
+/// order they were logged in since changes in a batch often share a time.
+pub fn undo_moves(changes: &[Change], batch: u64) -> Vec<Move> {
+	changes.iter().rev()
+		.filter(|it| it.batch == batch)
+		.map(|it| (it.squad_id, it.after.clone(), it.before.clone()))
+		.collect()
+}
+
+/// The moves that apply a batch again in the order it was first made.
+pub fn redo_moves(changes: &[Change], batch: u64) -> Vec<Move> {
+	changes.iter()
+		.filter(|it| it.batch == batch)
+		.map(|it| (it.squad_id, it.before.clone(), it.after.clone()))
+		.collect()
+}
+
+/// Reads the changes of a history file, oldest first.
+pub fn parse_lines(text: &str) -> Result<Vec<Change>, String> {
+	text.lines().enumerate()
+		.filter(|(_, line)| !line.trim().is_empty())
+		.map(|(index, line)| serde_json::from_str(line).map_err(|error| format!("Line {}: {}", index + 1, error)))
+		.collect()
+}
+
+/// Lays out changes as lines to append to a history file.
+pub fn to_lines(changes: &[Change]) -> String {
+	changes.iter()
+		.map(|it| format!("{}\n", serde_json::to_string(it).expect("Change to json")))
+		.collect()
+}
+
+/// The name of the user making changes.
+pub fn who() -> String {
+	env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use chad_core::core::Lot;
+	use chrono::NaiveDate;
+
+	use crate::vault::{LotBasis, SquadAnnex};
+
+	use super::{Change, Record, UndoStacks};
+
+	fn change(before: Option<Record>, after: Option<Record>) -> Change {
+		let at = NaiveDate::from_ymd_opt(2020, 1, 31).expect("Date").and_hms_opt(9, 30, 0).expect("Time");
+		Change { squad_id: 1, batch: 1, at, who: "pat".to_string(), before, after }
+	}
+
+	#[test]
+	fn describes_before_and_after() {
+		let lot = |shares| Record::Lot { lot_id: 10042, symbol: "VTI".to_string(), account: "IRA".to_string(), shares, basis: LotBasis::default() };
+		assert_eq!("Lot VTI #42: 10 sh in IRA -> 12 sh in IRA", change(Some(lot(10.0)), Some(lot(12.0))).describe());
+		assert_eq!("Lot VTI #42 removed: 10 sh in IRA", change(Some(lot(10.0)), None).describe());
+		let squad = Record::Squad { name: "Core".to_string(), owner: 5000 };
+		assert_eq!("Squad added: Core", change(None, Some(squad)).describe());
+	}
+
+	#[test]
+	fn lot_record_carries_basis_and_keeps_it_apart_from_shares() {
+		let lots = vec![Lot { squad_id: 1, id: 7, symbol: "VTI".to_string(), account: "IRA".to_string(), shares: 10.0 }];
+		let basis = LotBasis { cost: Some(1800.0), acquired: NaiveDate::from_ymd_opt(2019, 6, 3) };
+		let annex = SquadAnnex { lots: BTreeMap::from([(7, basis)]), ..SquadAnnex::default() };
+		let before = super::lot_record(&lots, &annex, 7).unwrap();
+		assert_eq!(Record::Lot { lot_id: 7, symbol: "VTI".to_string(), account: "IRA".to_string(), shares: 10.0, basis }, before);
+		assert_eq!(None, super::lot_record(&lots, &annex, 8));
+		let after = before.with_basis(LotBasis::default()).unwrap();
+		assert_eq!(Record::Lot { lot_id: 7, symbol: "VTI".to_string(), account: "IRA".to_string(), shares: 10.0, basis: LotBasis::default() }, after);
+		assert_eq!(None, Record::Squad { name: "Core".to_string(), owner: 5000 }.with_basis(basis));
+	}
+
+	#[test]
+	fn unspent_record_carries_account_split() {
+		let annex = SquadAnnex { cash: BTreeMap::from([("IRA".to_string(), 40.0), ("Joint".to_string(), 60.0)]), ..SquadAnnex::default() };
+		let record = super::unspent_record(100.0, &annex);
+		assert_eq!("$100 (IRA $40.0, Joint $60.0)", record.value());
+	}
+
+	#[test]
+	fn history_lines_round_trip_and_report_damage() {
+		let changes = vec![change(None, Some(Record::Squad { name: "Core".to_string(), owner: 5000 })), change(Some(Record::Order(vec!["VTI".to_string()])), None)];
+		let text = super::to_lines(&changes);
+		assert_eq!(2, text.lines().count());
+		assert_eq!(changes, super::parse_lines(&text).unwrap());
+		let damaged = format!("{}{{\"batch\":", text);
+		assert!(super::parse_lines(&damaged).unwrap_err().starts_with("Line 3:"));
+	}
+
+	#[test]
+	fn undo_and_redo_follow_log_order_within_a_batch() {
+		let lot = |shares| Record::Lot { lot_id: 7, symbol: "VTI".to_string(), account: "IRA".to_string(), shares, basis: LotBasis::default() };
+		let squad = Record::Squad { name: "Core".to_string(), owner: 5000 };
+		let other = Change { batch: 2, ..change(None, Some(Record::Order(vec![]))) };
+		// Every change shares a time so only the log order tells them apart.
+		let changes = vec![change(None, Some(squad.clone())), other, change(None, Some(lot(10.0))), change(Some(lot(10.0)), Some(lot(12.0)))];
+		let undo = super::undo_moves(&changes, 1);
+		assert_eq!(vec![(1, Some(lot(12.0)), Some(lot(10.0))), (1, Some(lot(10.0)), None), (1, Some(squad.clone()), None)], undo);
+		let redo = super::redo_moves(&changes, 1);
+		assert_eq!(vec![(1, None, Some(squad)), (1, None, Some(lot(10.0))), (1, Some(lot(10.0)), Some(lot(12.0)))], redo);
+	}
+
+	#[test]
+	fn new_edits_clear_redo() {
+		let mut stacks = UndoStacks::default();
+		stacks.push(1);
+		stacks.push(1);
+		stacks.push(2);
+		assert_eq!(vec![1, 2], stacks.undo);
+		stacks.undone();
+		assert_eq!((vec![1], vec![2]), (stacks.undo.clone(), stacks.redo.clone()));
+		stacks.redone();
+		stacks.undone();
+		stacks.push(3);
+		assert_eq!(UndoStacks { undo: vec![1, 3], redo: vec![] }, stacks);
+	}
+}
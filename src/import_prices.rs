@@ -0,0 +1,96 @@
+use std::fs;
+
+use yui::{AfterFlow, ArcYard, Cling, Create, Flow, Pack, SenderLink, yard};
+use yui::palette::StrokeColor;
+use yui::yard::ButtonState;
+
+use crate::{quotes, render, YardId};
+use crate::quotes::PriceImport;
+use crate::vault::Vault;
+
+#[derive(Clone, Debug)]
+pub struct State {
+	path: String,
+	import: Result<PriceImport, String>,
+}
+
+pub enum Action {
+	Close,
+	Submit,
+}
+
+pub struct Spark {
+	pub vault: Vault,
+}
+
+impl yui::Spark for Spark {
+	type State = State;
+	type Action = Action;
+	type Report = usize;
+
+	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
+		let path = self.vault.data_dir().join(quotes::QUOTES_FILE);
+		let import = fs::read_to_string(&path)
+			.map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+			.and_then(|text| quotes::parse_csv(&text))
+			.map(|quotes| quotes::plan_import(&self.vault.squads(), &quotes));
+		State { path: path.display().to_string(), import }
+	}
+
+	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
+		match action {
+			Action::Close => AfterFlow::Close(None),
+			Action::Submit => match &ctx.state().import {
+				Ok(import) => {
+					let saved = self.vault.batch(|| {
+						for change in &import.changes {
+							self.vault.set_price(change.squad_id, &change.symbol, change.new_price)?;
+						}
+						Ok(())
+					});
+					match saved {
+						Ok(()) => AfterFlow::Close(Some(import.changes.len())),
+						Err(error) => AfterFlow::Revise(State { import: Err(render::save_error(error)), ..ctx.state().clone() }),
+					}
+				}
+				Err(_) => AfterFlow::Ignore,
+			},
+		}
+	}
+
+	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let (content, can_submit) = match &state.import {
+			Err(message) => (yard::label(message, StrokeColor::CommentOnBackground, Cling::LeftTop), false),
+			Ok(import) => {
+				let mut items = import.changes.iter().map(|change| {
+					let mut text = format!("{} in {}: {} -> {}", change.symbol, change.squad_name, change.old_price, change.new_price);
+					if let Some(date) = &change.date {
+						text.push_str(&format!(" as of {}", date));
+					}
+					(1, yard::label(text, StrokeColor::BodyOnBackground, Cling::Left))
+				}).collect::<Vec<_>>();
+				if items.is_empty() {
+					items.push((1, yard::label("No price changes", StrokeColor::CommentOnBackground, Cling::Left)));
+				}
+				if !import.unknown.is_empty() {
+					let text = format!("Unknown: {}", import.unknown.join(", "));
+					items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+				}
+				if !import.conflicts.is_empty() {
+					let text = format!("Skipped: {}", import.conflicts.join(", "));
+					items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+				}
+				(yard::list(YardId::ImportPricesList.as_i32(), 0, items), !import.changes.is_empty())
+			}
+		};
+		let content = content.pack_top(2, yard::label(&state.path, StrokeColor::CommentOnBackground, Cling::LeftTop));
+		let yard = render::dialog(
+			"Import Prices",
+			link.map(|_| Action::Close),
+			if can_submit { ButtonState::enabled(link.map(|_| Action::Submit)) } else { ButtonState::disabled() },
+			None,
+			content,
+		);
+		Some(yard)
+	}
+}
@@ -1,35 +1,76 @@
 extern crate chad_core;
+extern crate chrono;
 extern crate dirs;
 extern crate echo_lib;
 extern crate rand;
+extern crate serde;
+extern crate serde_json;
 extern crate stringedit;
 extern crate yui;
 
+use std::env;
 use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use chad_core::chad::Chad;
 use yui::app;
 
+use crate::quotes::FileQuotes;
+use crate::vault::Vault;
+
+mod cli;
 mod edit_squad;
 mod edit_member;
 mod edit_unspent;
 mod pick_squad;
 mod edit_lot;
+mod edit_prices;
+mod import_prices;
+mod rebalance;
+mod edit_targets;
+mod record_trade;
+mod squad_history;
+mod pick_profile;
 pub(crate) mod sprint;
 pub(crate) mod render;
 pub(crate) mod compute;
-
-const OWNER: u64 = 5000;
+pub(crate) mod vault;
+pub(crate) mod quotes;
+pub(crate) mod csv;
+pub(crate) mod export;
+pub(crate) mod history;
+pub(crate) mod positions;
+pub(crate) mod backup;
 
 fn main() -> Result<(), Box<dyn Error>> {
-	let data_dir = {
-		let mut dir = dirs::home_dir().expect("Home exists");
-		dir.push(format!(".{}", APP_NAME));
-		dir
+	let mut args = env::args().skip(1).collect::<Vec<_>>();
+	let profile = cli::take_flag(&mut args, "--profile")?;
+	let data_dir = match cli::take_flag(&mut args, "--data-dir")?.or_else(|| env::var(DATA_DIR_VAR).ok()) {
+		Some(dir) => PathBuf::from(dir),
+		None => {
+			let mut dir = dirs::home_dir().expect("Home exists");
+			dir.push(format!(".{}", APP_NAME));
+			dir
+		}
 	};
-	let chad = Chad::connect(&data_dir);
-	let spark = pick_squad::Spark { chad };
-	app::run(spark, None)?;
+	if let Some("backup") | Some("restore") = args.first().map(|it| it.as_str()) {
+		// Archives work on the files so the store must not be open.
+		return cli::run_archive(&data_dir, &args);
+	}
+	fs::create_dir_all(&data_dir)?;
+	let vault = Vault::connect(&data_dir, profile.as_deref()).map_err(|error| match error.kind() {
+		io::ErrorKind::NotFound => format!("{}. Add it with: trove add-profile <name>", error),
+		_ => error.to_string(),
+	})?;
+	if args.is_empty() {
+		let quotes = Arc::new(FileQuotes::in_dir(vault.data_dir()));
+		let spark = pick_squad::Spark { vault, quotes };
+		app::run(spark, None)?;
+	} else {
+		cli::run(&vault, &args)?;
+	}
 	Ok(())
 }
 
@@ -43,7 +84,26 @@ enum YardId {
 	NameField,
 	PickSquadList,
 	MemberLotList,
-	UnspentEdit,
+	UnspentList,
+	UnspentEdit(usize),
+	PricesList,
+	PriceEdit(usize),
+	ImportPricesList,
+	RebalanceList,
+	TargetsList,
+	TargetRatioEdit,
+	TargetEdit(usize),
+	BandAbsoluteEdit,
+	BandRelativeEdit,
+	LotCostEdit,
+	LotAcquiredEdit,
+	TradeAccountEdit,
+	TradeSharesEdit,
+	TradePriceEdit,
+	TradeDateEdit,
+	HistoryList,
+	ProfileList,
+	ProfileNameEdit,
 }
 
 impl YardId {
@@ -58,11 +118,33 @@ impl YardId {
 			YardId::NameField => 707,
 			YardId::PickSquadList => 708,
 			YardId::MemberLotList => 709,
-			YardId::UnspentEdit => 710,
+			YardId::UnspentList => 710,
+			YardId::PricesList => 711,
+			YardId::ImportPricesList => 712,
+			YardId::RebalanceList => 713,
+			YardId::TargetsList => 714,
+			YardId::TargetRatioEdit => 715,
+			YardId::BandAbsoluteEdit => 716,
+			YardId::BandRelativeEdit => 717,
+			YardId::LotCostEdit => 718,
+			YardId::LotAcquiredEdit => 719,
+			YardId::TradeAccountEdit => 720,
+			YardId::TradeSharesEdit => 721,
+			YardId::TradePriceEdit => 722,
+			YardId::TradeDateEdit => 723,
+			YardId::HistoryList => 724,
+			YardId::ProfileList => 725,
+			YardId::ProfileNameEdit => 726,
+			YardId::PriceEdit(index) => 7000 + *index as i32,
+			YardId::UnspentEdit(index) => 8000 + *index as i32,
+			YardId::TargetEdit(index) => 9000 + *index as i32,
 		}
 	}
 }
 
+/// Names a data directory to use in place of the one under home.
+const DATA_DIR_VAR: &str = "TROVE_DATA_DIR";
+
 #[cfg(debug_assertions)]
 const APP_NAME: &str = "chad-debug";
 #[cfg(not(debug_assertions))]
@@ -0,0 +1,101 @@
+use stringedit::{StringEdit, Validity};
+use yui::{AfterFlow, ArcYard, Cling, Confine, Create, Flow, SenderLink, StringEditAction, yard};
+use yui::palette::StrokeColor;
+use yui::yard::ButtonState;
+
+use crate::{render, YardId};
+use crate::vault::Vault;
+
+#[derive(Clone, Debug)]
+pub struct State {
+	profiles: Vec<String>,
+	current: String,
+	name_edit: StringEdit,
+	error: Option<String>,
+}
+
+impl State {
+	fn name(&self) -> String {
+		self.name_edit.chars.iter().cloned().collect::<String>().trim().to_owned()
+	}
+}
+
+pub enum Action {
+	Close,
+	Pick(String),
+	EditName(StringEditAction),
+	Submit,
+}
+
+pub struct Spark {
+	pub vault: Vault,
+}
+
+impl yui::Spark for Spark {
+	type State = State;
+	type Action = Action;
+	type Report = String;
+
+	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
+		State {
+			profiles: self.vault.profiles(),
+			current: self.vault.profile(),
+			name_edit: StringEdit::empty(Validity::NotEmpty),
+			error: None,
+		}
+	}
+
+	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
+		match action {
+			Action::Close => AfterFlow::Close(None),
+			Action::Pick(profile) => match self.vault.switch_profile(&profile) {
+				Ok(()) => AfterFlow::Close(Some(profile)),
+				Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+			},
+			Action::EditName(action) => {
+				let mut state = ctx.state().clone();
+				state.name_edit = state.name_edit.edit(action);
+				AfterFlow::Revise(state)
+			}
+			Action::Submit => {
+				let name = ctx.state().name();
+				if name.is_empty() {
+					AfterFlow::Ignore
+				} else {
+					match self.vault.add_profile(&name).and_then(|_| self.vault.switch_profile(&name)) {
+						Ok(()) => AfterFlow::Close(Some(name)),
+						Err(error) => AfterFlow::Revise(State { error: Some(render::save_error(error)), ..ctx.state().clone() }),
+					}
+				}
+			}
+		}
+	}
+
+	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let mut items = state.profiles.iter().map(|profile| {
+			let yard = if profile == &state.current {
+				yard::label(format!("{} (current)", profile), StrokeColor::CommentOnBackground, Cling::Left)
+			} else {
+				let pick = link.map({
+					let profile = profile.to_owned();
+					move |_| Action::Pick(profile.to_owned())
+				});
+				yard::button(profile, ButtonState::enabled(pick)).confine_width(profile.len() as i32 + 6, Cling::Left)
+			};
+			(1, yard)
+		}).collect::<Vec<_>>();
+		items.push((1, yard::empty()));
+		items.push((3, yard::textfield(YardId::ProfileNameEdit.as_i32(), "New profile", state.name_edit.clone(), link.map(Action::EditName))));
+		if let Some(error) = &state.error {
+			items.push((1, yard::label(error, StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		let yard = render::dialog(
+			"Profiles",
+			link.map(|_| Action::Close),
+			if state.name().is_empty() { ButtonState::disabled() } else { ButtonState::enabled(link.map(|_| Action::Submit)) },
+			None,
+			yard::list(YardId::ProfileList.as_i32(), 0, items),
+		);
+		Some(yard)
+	}
+}
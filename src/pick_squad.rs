@@ -1,32 +1,89 @@
-use chad_core::chad::Chad;
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use chad_core::core::Squad;
-use yui::{AfterFlow, ArcYard, Cling, Create, Flow, Padding, SenderLink, yard};
+use yui::{AfterFlow, ArcYard, Cling, Create, Flow, Pack, Padding, SenderLink, yard};
 use yui::palette::StrokeColor;
-use yui::yard::{MuxButton, Pressable};
+use yui::yard::{ButtonState, MuxButton, Pressable};
 
-use crate::{edit_lot, edit_member, edit_unspent, OWNER, render, YardId};
-use crate::edit_squad::EditSquadSpark;
+use crate::{compute, edit_lot, edit_member, edit_prices, edit_targets, edit_unspent, export, pick_profile, rebalance, record_trade, render, sprint, squad_history, YardId};
+use crate::edit_squad::{EditSquadSpark, Mode};
+use crate::quotes::{self, QuoteSource};
+use crate::render::SquadLinks;
+use crate::vault::{SquadAnnex, Vault};
 
 #[derive(Clone, Debug)]
 pub struct State {
 	pub squads: Vec<Squad>,
+	pub annexes: HashMap<u64, SquadAnnex>,
 	pub pick: Option<(u64, Option<String>)>,
+	pub notice: Option<String>,
+	pub can_undo: bool,
+	pub can_redo: bool,
+	pub profile: String,
 }
 
 #[derive(Debug)]
 pub enum Action {
 	AddSquad,
-	SquadAdded(u64),
+	EditSquad(u64),
+	DuplicateSquad(u64),
+	SquadChanged(u64),
 	PickSquad(u64),
 	AddMember(u64),
-	MemberAdded((u64, String)),
+	MemberAdded((u64, Option<String>)),
 	PickMember(u64, String),
+	MoveMember((u64, String, i32)),
+	EditMember(u64, String),
+	MemberEdited((u64, Option<String>)),
 	EditLot((u64, String, Option<u64>)),
-	SetUnspent((u64, Option<f64>)),
+	RecordTrade(u64, String),
+	SetUnspent(u64),
+	EditPrices(u64),
+	EditTargets(u64),
+	RefreshPrices,
+	Rebalance(u64),
+	ViewHistory(u64),
+	ExportSquad(u64),
+	Undo,
+	Redo,
+	PickProfile,
+	ProfilePicked(String),
 }
 
 #[derive(Clone, Debug)]
-pub struct Spark { pub chad: Chad }
+pub struct Spark {
+	pub vault: Vault,
+	pub quotes: Arc<dyn QuoteSource>,
+}
+
+impl Spark {
+	fn load(&self, pick: Option<(u64, Option<String>)>, notice: Option<String>) -> State {
+		let squads = self.vault.squads();
+		let annexes = squads.iter().map(|it| (it.id, self.vault.squad_annex(it.id))).collect();
+		State { squads, annexes, pick, notice, can_undo: self.vault.can_undo(), can_redo: self.vault.can_redo(), profile: self.vault.profile() }
+	}
+
+	/// Updates every squad price the quote source knows and returns the
+	/// number of prices that moved.
+	fn refresh_prices(&self) -> Result<usize, String> {
+		let mut moves = Vec::new();
+		for squad in self.vault.squads() {
+			let refreshed = quotes::refresh_prices(self.quotes.as_ref(), &squad.prices)?;
+			for (symbol, price) in refreshed {
+				if squad.prices.get(&symbol) != Some(&price) {
+					moves.push((squad.id, symbol, price));
+				}
+			}
+		}
+		self.vault.batch(|| {
+			for (squad_id, symbol, price) in &moves {
+				self.vault.set_price(*squad_id, symbol, *price)?;
+			}
+			Ok(moves.len())
+		}).map_err(|error| error.to_string())
+	}
+}
 
 impl yui::Spark for Spark {
 	type State = State;
@@ -34,69 +91,161 @@ impl yui::Spark for Spark {
 	type Report = ();
 
 	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
-		let snap = self.chad.snap();
-		let squads = snap.squads(OWNER);
-		let pick = squads.first().map(|it| it.id).map(|it| (it, None));
-		State { squads, pick }
+		let state = self.load(None, None);
+		let pick = state.squads.first().map(|it| it.id).map(|it| (it, None));
+		State { pick, ..state }
 	}
 
 	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
 		match action {
 			Action::AddSquad => {
-				let spark = EditSquadSpark { chad: self.chad.clone(), owner: OWNER };
-				ctx.start_prequel(spark, ctx.link().map(Action::SquadAdded));
+				let spark = EditSquadSpark { vault: self.vault.clone(), mode: Mode::Add };
+				ctx.start_prequel(spark, ctx.link().map(Action::SquadChanged));
 				AfterFlow::Ignore
 			}
-			Action::SquadAdded(id) => {
-				let snap = self.chad.snap();
-				let state = State {
-					squads: snap.squads(OWNER),
-					pick: Some((id, None)),
-				};
-				AfterFlow::Revise(state)
+			Action::EditSquad(squad_id) => {
+				let spark = EditSquadSpark { vault: self.vault.clone(), mode: Mode::Rename(squad_id) };
+				ctx.start_prequel(spark, ctx.link().map(Action::SquadChanged));
+				AfterFlow::Ignore
+			}
+			Action::DuplicateSquad(squad_id) => {
+				let spark = EditSquadSpark { vault: self.vault.clone(), mode: Mode::Duplicate(squad_id) };
+				ctx.start_prequel(spark, ctx.link().map(Action::SquadChanged));
+				AfterFlow::Ignore
+			}
+			Action::SquadChanged(id) => {
+				let state = self.load(Some((id, None)), None);
+				if state.squads.iter().any(|it| it.id == id) {
+					AfterFlow::Revise(state)
+				} else {
+					let pick = state.squads.first().map(|it| (it.id, None));
+					AfterFlow::Revise(State { pick, ..state })
+				}
 			}
 			Action::PickSquad(id) => {
-				let snap = self.chad.snap();
-				let squads = snap.squads(OWNER);
-				let squad_exists = squads.iter().any(|it| it.id == id);
+				let state = self.load(Some((id, None)), None);
+				let squad_exists = state.squads.iter().any(|it| it.id == id);
 				match squad_exists {
-					true => AfterFlow::Revise(State { squads, pick: Some((id, None)) }),
+					true => AfterFlow::Revise(state),
 					false => AfterFlow::Ignore,
 				}
 			}
 			Action::AddMember(squad_id) => {
-				let spark = edit_member::Spark { chad: self.chad.clone(), squad_id };
+				let spark = edit_member::Spark { vault: self.vault.clone(), squad_id, member_symbol: None };
 				ctx.start_prequel(spark, ctx.link().map(Action::MemberAdded));
 				AfterFlow::Ignore
 			}
 			Action::MemberAdded((squad_id, _member_id)) => {
-				let snap = self.chad.snap();
-				let mut state = ctx.state().clone();
-				state.squads = snap.squads(OWNER);
-				state.pick = Some((squad_id, None));
-				AfterFlow::Revise(state)
+				let notice = ctx.state().notice.clone();
+				AfterFlow::Revise(self.load(Some((squad_id, None)), notice))
+			}
+			Action::EditMember(squad_id, member_symbol) => {
+				let spark = edit_member::Spark { vault: self.vault.clone(), squad_id, member_symbol: Some(member_symbol) };
+				ctx.start_prequel(spark, ctx.link().map(Action::MemberEdited));
+				AfterFlow::Ignore
+			}
+			Action::MemberEdited((squad_id, member_symbol)) => {
+				let notice = ctx.state().notice.clone();
+				AfterFlow::Revise(self.load(Some((squad_id, member_symbol)), notice))
 			}
 			Action::PickMember(squad_id, member_symbol) => {
-				let snap = self.chad.snap();
-				let mut state = ctx.state().clone();
-				state.squads = snap.squads(OWNER);
-				state.pick = Some((squad_id, Some(member_symbol)));
-				AfterFlow::Revise(state)
+				let notice = ctx.state().notice.clone();
+				AfterFlow::Revise(self.load(Some((squad_id, Some(member_symbol))), notice))
+			}
+			Action::MoveMember((squad_id, symbol, offset)) => {
+				let state = ctx.state();
+				match state.squads.iter().find(|it| it.id == squad_id) {
+					None => AfterFlow::Ignore,
+					Some(squad) => {
+						let annex = state.annexes.get(&squad_id).cloned().unwrap_or_default();
+						let order = compute::drifts(squad, &annex).iter().map(|it| it.symbol().to_string()).collect::<Vec<_>>();
+						let notice = match self.vault.set_member_order(squad_id, compute::move_member(&order, &symbol, offset)) {
+							Ok(()) => state.notice.clone(),
+							Err(error) => Some(render::save_error(error)),
+						};
+						AfterFlow::Revise(self.load(Some((squad_id, None)), notice))
+					}
+				}
 			}
 			Action::EditLot((squad_id, member_symbol, lot_id)) => {
-				let spark = edit_lot::Spark { chad: self.chad.clone(), squad_id, member_symbol, lot_id };
+				let spark = edit_lot::Spark { vault: self.vault.clone(), squad_id, member_symbol, lot_id };
 				ctx.start_prequel(spark, ctx.link().map(|(squad_id, symbol, _)| Action::PickMember(squad_id, symbol)));
 				AfterFlow::Ignore
 			}
-			Action::SetUnspent((squad_id, unspent)) => {
+			Action::RecordTrade(squad_id, symbol) => {
+				let spark = record_trade::Spark { vault: self.vault.clone(), squad_id, symbol };
+				ctx.start_prequel(spark, ctx.link().map(|(squad_id, symbol)| Action::PickMember(squad_id, symbol)));
+				AfterFlow::Ignore
+			}
+			Action::SetUnspent(squad_id) => {
 				let spark = edit_unspent::Spark {
-					chad: self.chad.clone(),
+					vault: self.vault.clone(),
 					squad_id,
-					unspent,
 				};
 				ctx.start_prequel(spark, ctx.link().map(|(squad_id, _)| Action::PickSquad(squad_id)));
 				AfterFlow::Ignore
 			}
+			Action::EditPrices(squad_id) => {
+				let spark = edit_prices::Spark { vault: self.vault.clone(), squad_id };
+				ctx.start_prequel(spark, ctx.link().map(Action::PickSquad));
+				AfterFlow::Ignore
+			}
+			Action::EditTargets(squad_id) => {
+				let spark = edit_targets::Spark { vault: self.vault.clone(), squad_id };
+				ctx.start_prequel(spark, ctx.link().map(Action::PickSquad));
+				AfterFlow::Ignore
+			}
+			Action::RefreshPrices => {
+				let notice = match self.refresh_prices() {
+					Ok(changed) => format!("Refreshed {} price(s)", changed),
+					Err(message) => format!("Refresh failed: {}", message),
+				};
+				let pick = ctx.state().pick.clone();
+				AfterFlow::Revise(self.load(pick, Some(notice)))
+			}
+			Action::Rebalance(squad_id) => {
+				let spark = rebalance::Spark { vault: self.vault.clone(), squad_id };
+				ctx.start_prequel(spark, ctx.link().map(Action::PickSquad));
+				AfterFlow::Ignore
+			}
+			Action::ViewHistory(squad_id) => {
+				let spark = squad_history::Spark { vault: self.vault.clone(), squad_id };
+				ctx.start_prequel(spark, ctx.link().map(Action::PickSquad));
+				AfterFlow::Ignore
+			}
+			Action::ExportSquad(squad_id) => {
+				let squad = self.vault.squad(squad_id).expect("Squad exists");
+				let dir = self.vault.data_dir().join(export::EXPORTS_DIR);
+				let notice = match export::write_squad(&dir, &squad, &self.vault.squad_annex(squad_id)) {
+					Ok((csv_path, json_path)) => format!("Exported to {} and {}", csv_path.display(), json_path.display()),
+					Err(error) => format!("Export failed: {}", error),
+				};
+				let pick = ctx.state().pick.clone();
+				AfterFlow::Revise(self.load(pick, Some(notice)))
+			}
+			Action::Undo | Action::Redo => {
+				let (count, verb) = match action {
+					Action::Undo => (self.vault.undo(), "Undid"),
+					_ => (self.vault.redo(), "Redid"),
+				};
+				let notice = match count {
+					Ok(count) => format!("{} {} change(s)", verb, count),
+					Err(error) => render::save_error(error),
+				};
+				// The picked member may not survive the change.
+				let pick = ctx.state().pick.as_ref().map(|(squad_id, _)| (*squad_id, None));
+				AfterFlow::Revise(self.load(pick, Some(notice)))
+			}
+			Action::PickProfile => {
+				let spark = pick_profile::Spark { vault: self.vault.clone() };
+				ctx.start_prequel(spark, ctx.link().map(Action::ProfilePicked));
+				AfterFlow::Ignore
+			}
+			Action::ProfilePicked(profile) => {
+				let state = self.load(None, Some(format!("Switched to {}", profile)));
+				let pick = state.squads.first().map(|it| (it.id, None));
+				AfterFlow::Revise(State { pick, ..state })
+			}
 		}
 	}
 
@@ -111,25 +260,58 @@ impl yui::Spark for Spark {
 		let center = match squad {
 			None => yard::label("Add a squad", StrokeColor::CommentOnBackground, Cling::Center),
 			Some(squad) => match member {
-				None => render::squad(
-					squad,
-					link.map({
+				None => render::squad(squad, &state.annexes.get(&squad.id).cloned().unwrap_or_default(), SquadLinks {
+					add_member: link.map({
 						let squad_id = squad.id;
 						move |_| Action::AddMember(squad_id)
 					}),
-					link.map(move |(squad_id, symbol)| Action::PickMember(squad_id, symbol)),
-					link.map(Action::SetUnspent),
-				),
+					view_member: link.map(move |(squad_id, symbol)| Action::PickMember(squad_id, symbol)),
+					move_member: link.map(Action::MoveMember),
+					set_unspent: link.map(Action::SetUnspent),
+					edit_squad: link.map(Action::EditSquad),
+					duplicate_squad: link.map(Action::DuplicateSquad),
+					edit_prices: link.map(Action::EditPrices),
+					edit_targets: link.map(Action::EditTargets),
+					refresh_prices: link.map(|_| Action::RefreshPrices),
+					rebalance: link.map(Action::Rebalance),
+					view_history: link.map(Action::ViewHistory),
+					export_squad: link.map(Action::ExportSquad),
+				}),
 				Some(member) => {
 					let index = squad.members.iter().position(|it| &it.symbol == member).expect("Member index");
 					let member = &squad.members[index];
-					render::member_view(member, &squad, link.map(Action::EditLot))
+					render::member_view(
+						member,
+						squad,
+						&state.annexes.get(&squad.id).cloned().unwrap_or_default(),
+						link.map(Action::EditLot),
+						link.map(|(squad_id, symbol)| Action::EditMember(squad_id, symbol)),
+						link.map(|(squad_id, symbol)| Action::RecordTrade(squad_id, symbol)),
+					)
 				}
 			},
 		};
+		let status = {
+			let notice = match &state.notice {
+				None => yard::empty(),
+				Some(notice) => yard::label(notice, StrokeColor::CommentOnBackground, Cling::Left).pad_cols(1),
+			};
+			let undo = yard::button("Undo", if state.can_undo { ButtonState::enabled(link.map(|_| Action::Undo)) } else { ButtonState::disabled() });
+			let redo = yard::button("Redo", if state.can_redo { ButtonState::enabled(link.map(|_| Action::Redo)) } else { ButtonState::disabled() });
+			let profile_text = format!("Profile: {}", state.profile);
+			let profile = yard::button(&profile_text, ButtonState::enabled(link.map(|_| Action::PickProfile)));
+			notice.pack_left(8, redo).pack_left(8, undo).pack_left(profile_text.len() as i32 + 6, profile)
+		};
+		let center = center.pack_bottom(1, status);
 		let sources = squads.iter().map(|it| {
 			let squad_id = it.id;
-			let squad_name = format!("{}", it.name);
+			let weighting = state.annexes.get(&squad_id).map(|it| it.weighting.to_owned()).unwrap_or_default();
+			let symbols = it.members.iter().map(|it| it.symbol.as_str()).collect::<Vec<_>>();
+			// Percent targets that miss 100% leave drift and rebalancing wrong.
+			let squad_name = match compute::broken_percent_total(&weighting, &symbols) {
+				None => it.name.to_string(),
+				Some(total) => format!("{} (targets {}%)", it.name, sprint::amount_prefix(total, "")),
+			};
 			let yard =
 				yard::label(squad_name, StrokeColor::BodyOnBackground, Cling::Center)
 					.pad_cols(1)
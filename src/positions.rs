@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chad_core::core::Lot;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::compute::{self, LotLeft, Order, Side, SHARE_TOLERANCE};
+use crate::csv::{parse_number, split_line};
+use crate::vault::{LotBasis, SquadAnnex};
+
+pub const COLUMNS_FILE: &str = "positions-columns.json";
+
+/// Header names of the columns that hold each field of a position. Names
+/// match without regard to case.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Columns {
+	pub account: String,
+	pub symbol: String,
+	pub shares: String,
+	/// Total cost basis of the position.
+	#[serde(default)]
+	pub cost: Option<String>,
+}
+
+impl Default for Columns {
+	fn default() -> Self {
+		Columns {
+			account: "account".to_string(),
+			symbol: "symbol".to_string(),
+			shares: "shares".to_string(),
+			cost: Some("cost".to_string()),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+	pub account: String,
+	pub symbol: String,
+	pub shares: f64,
+	pub cost: Option<f64>,
+}
+
+/// Reads positions from a csv with a header row. Rows for the same account
+/// and symbol are added together and rows without a symbol or shares, like
+/// cash and total lines, are skipped.
+pub fn parse_csv(text: &str, columns: &Columns) -> Result<Vec<Position>, String> {
+	let mut lines = text.lines().enumerate()
+		.filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'));
+	let header = match lines.next() {
+		None => return Ok(Vec::new()),
+		Some((_, line)) => split_line(line),
+	};
+	let column = |name: &str| header.iter().position(|it| it.eq_ignore_ascii_case(name.trim()));
+	let account_index = column(&columns.account).ok_or_else(|| format!("No {} column", columns.account))?;
+	let symbol_index = column(&columns.symbol).ok_or_else(|| format!("No {} column", columns.symbol))?;
+	let shares_index = column(&columns.shares).ok_or_else(|| format!("No {} column", columns.shares))?;
+	let cost_index = match &columns.cost {
+		None => None,
+		Some(cost) => column(cost),
+	};
+	let mut positions = BTreeMap::<(String, String), Position>::new();
+	for (index, line) in lines {
+		let fields = split_line(line);
+		let field = |index: usize| fields.get(index).map(|it| it.as_str()).unwrap_or("");
+		let symbol = field(symbol_index).to_uppercase();
+		if symbol.is_empty() || field(shares_index).is_empty() {
+			continue;
+		}
+		let shares = parse_number(field(shares_index)).ok_or_else(|| format!("Line {}: bad shares {:?}", index + 1, field(shares_index)))?;
+		let cost = match cost_index.map(field).filter(|it| !it.is_empty()) {
+			None => None,
+			Some(cost) => Some(parse_number(cost).ok_or_else(|| format!("Line {}: bad cost {:?}", index + 1, cost))?),
+		};
+		let account = field(account_index).to_string();
+		let position = positions.entry((account.to_owned(), symbol.to_owned()))
+			.or_insert(Position { account, symbol, shares: 0.0, cost: Some(0.0) });
+		position.shares += shares;
+		position.cost = match (position.cost, cost) {
+			(Some(total), Some(cost)) => Some(total + cost),
+			_ => None,
+		};
+	}
+	Ok(positions.into_values().collect())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+	/// Adds and updates lots but leaves lots missing from the file alone.
+	Merge,
+	/// Also removes lots in each imported account that the file leaves out.
+	Replace,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LotChange {
+	Add { symbol: String, account: String, shares: f64, cost: Option<f64> },
+	/// Sets a lot's shares and, when there is one, its new basis.
+	Update { lot_id: u64, symbol: String, account: String, old_shares: f64, shares: f64, basis: Option<LotBasis> },
+	Remove { lot_id: u64, symbol: String, account: String, shares: f64 },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LotImport {
+	pub changes: Vec<LotChange>,
+	/// Symbols in the file that are not members of the squad.
+	pub unknown: Vec<String>,
+	/// Positions left alone because their cost cannot be split among
+	/// their lots.
+	pub conflicts: Vec<String>,
+}
+
+/// Costs closer than half a cent are the same.
+const COST_TOLERANCE: f64 = 0.005;
+
+/// Matches positions against a squad's lots. Lots keep their basis: a
+/// smaller position trims them oldest first like a recorded sell and a
+/// larger one adds a lot for the extra shares. Positions that already match
+/// their lots and zero positions without lots are left out.
+pub fn plan_import(lots: &[Lot], annex: &SquadAnnex, members: &[String], positions: &[Position], mode: Mode, today: NaiveDate) -> LotImport {
+	let mut import = LotImport::default();
+	for position in positions {
+		if !members.contains(&position.symbol) {
+			if !import.unknown.contains(&position.symbol) {
+				import.unknown.push(position.symbol.to_owned());
+			}
+			continue;
+		}
+		match plan_position(lots, annex, position, today) {
+			Ok(changes) => import.changes.extend(changes),
+			Err(conflict) => import.conflicts.push(format!("{} {}: {}", position.account, position.symbol, conflict)),
+		}
+	}
+	if mode == Mode::Replace {
+		let accounts = positions.iter().map(|it| it.account.as_str()).collect::<BTreeSet<_>>();
+		let left_out = lots.iter().filter(|lot| {
+			accounts.contains(lot.account.as_str())
+				&& !positions.iter().any(|it| it.account == lot.account && it.symbol == lot.symbol)
+		});
+		for lot in left_out {
+			import.changes.push(remove(lot));
+		}
+	}
+	import
+}
+
+fn plan_position(lots: &[Lot], annex: &SquadAnnex, position: &Position, today: NaiveDate) -> Result<Vec<LotChange>, String> {
+	let held = lots.iter().filter(|it| it.symbol == position.symbol && it.account == position.account).collect::<Vec<_>>();
+	let held_shares = held.iter().map(|it| it.shares).sum::<f64>();
+	let lot_cost = |lot: &&Lot| annex.lots.get(&lot.id).and_then(|it| it.cost);
+	let held_cost = held.iter().map(lot_cost).sum::<Option<f64>>();
+	let basis = |lot: &Lot| annex.lots.get(&lot.id).copied().unwrap_or_default();
+	if !(position.shares.is_finite() && position.shares >= 0.0) {
+		return Err(format!("shares must be 0 or more, not {}", position.shares));
+	}
+	if held.is_empty() && position.shares < SHARE_TOLERANCE {
+		return Ok(Vec::new());
+	}
+	if held.is_empty() {
+		let add = LotChange::Add { symbol: position.symbol.to_owned(), account: position.account.to_owned(), shares: position.shares, cost: position.cost };
+		return Ok(vec![add]);
+	}
+	if (held_shares - position.shares).abs() < SHARE_TOLERANCE {
+		return match (position.cost, held.as_slice()) {
+			(None, _) => Ok(Vec::new()),
+			(Some(cost), _) if held_cost.map(|it| (it - cost).abs() < COST_TOLERANCE) == Some(true) => Ok(Vec::new()),
+			(Some(cost), [lot]) => Ok(vec![update(lot, lot.shares, Some(LotBasis { cost: Some(cost), ..basis(lot) }))]),
+			(Some(cost), _) => Err(cost_conflict(cost, held_cost, held.len())),
+		};
+	}
+	if position.shares < held_shares {
+		let order = Order { side: Side::Sell, symbol: position.symbol.to_owned(), account: position.account.to_owned(), shares: held_shares - position.shares, price: 0.0 };
+		let lefts = compute::sell_lots(&order, lots, annex, today)?;
+		let mut kept = held.iter()
+			.filter(|it| !lefts.iter().any(|left| left_id(left) == it.id))
+			.map(lot_cost)
+			.sum::<Option<f64>>();
+		let mut changes = Vec::new();
+		for left in lefts {
+			let lot = held.iter().find(|it| it.id == left_id(&left)).expect("Held lot");
+			match left {
+				LotLeft::Kept { shares, basis: left_basis, .. } => {
+					let left_basis = match (position.cost, held.as_slice()) {
+						(Some(cost), [_]) => Some(LotBasis { cost: Some(cost), ..basis(lot) }),
+						_ => left_basis,
+					};
+					kept = kept.and_then(|kept| Some(kept + left_basis?.cost?));
+					changes.push(update(lot, shares, left_basis));
+				}
+				LotLeft::Emptied { .. } => changes.push(remove(lot)),
+			}
+		}
+		return match position.cost {
+			Some(cost) if kept.map(|it| (it - cost).abs() < COST_TOLERANCE) != Some(true) => Err(cost_conflict(cost, kept, held.len())),
+			_ => Ok(changes),
+		};
+	}
+	let cost = match (position.cost, held_cost) {
+		(None, _) => None,
+		(Some(cost), Some(held_cost)) if cost + COST_TOLERANCE > held_cost => Some((cost - held_cost).max(0.0)),
+		(Some(cost), held_cost) => return Err(cost_conflict(cost, held_cost, held.len())),
+	};
+	Ok(vec![LotChange::Add { symbol: position.symbol.to_owned(), account: position.account.to_owned(), shares: position.shares - held_shares, cost }])
+}
+
+fn cost_conflict(cost: f64, lots_cost: Option<f64>, lot_count: usize) -> String {
+	match lots_cost {
+		Some(lots_cost) => format!("cost {} does not match {} in {} lot(s)", cost, lots_cost, lot_count),
+		None => format!("cost {} cannot be split among {} lot(s) missing a cost", cost, lot_count),
+	}
+}
+
+fn left_id(left: &LotLeft) -> u64 {
+	match left {
+		LotLeft::Kept { lot_id, .. } | LotLeft::Emptied { lot_id } => *lot_id,
+	}
+}
+
+fn update(lot: &Lot, shares: f64, basis: Option<LotBasis>) -> LotChange {
+	LotChange::Update { lot_id: lot.id, symbol: lot.symbol.to_owned(), account: lot.account.to_owned(), old_shares: lot.shares, shares, basis }
+}
+
+fn remove(lot: &Lot) -> LotChange {
+	LotChange::Remove { lot_id: lot.id, symbol: lot.symbol.to_owned(), account: lot.account.to_owned(), shares: lot.shares }
+}
+
+#[cfg(test)]
+mod tests {
+	use chad_core::core::Lot;
+	use chrono::NaiveDate;
+
+	use crate::vault::{LotBasis, SquadAnnex};
+
+	use super::{Columns, LotChange, LotImport, Mode, Position};
+
+	fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+		NaiveDate::from_ymd_opt(year, month, day).expect("Date")
+	}
+
+	fn today() -> NaiveDate {
+		date(2020, 12, 1)
+	}
+
+	fn position(account: &str, symbol: &str, shares: f64, cost: Option<f64>) -> Position {
+		Position { account: account.to_string(), symbol: symbol.to_string(), shares, cost }
+	}
+
+	fn lot(id: u64, account: &str, symbol: &str, shares: f64) -> Lot {
+		Lot { squad_id: 1, id, symbol: symbol.to_string(), account: account.to_string(), shares }
+	}
+
+	#[test]
+	fn parse_csv_maps_columns_and_skips_totals() {
+		let columns = Columns {
+			account: "Account Name".to_string(),
+			symbol: "Symbol".to_string(),
+			shares: "Quantity".to_string(),
+			cost: Some("Cost Basis Total".to_string()),
+		};
+		let text = "Account Name,Symbol,Description,Quantity,Cost Basis Total\n\
+			IRA,vti,\"Vanguard Total Stock Market, ETF\",10,\"$1,800.00\"\n\
+			IRA,VTI,Vanguard,2,\n\
+			Joint,BND,Vanguard Bond,\"1,000\",$80000\n\
+			Joint,Total,,,\n";
+		let positions = super::parse_csv(text, &columns).unwrap();
+		assert_eq!(vec![position("IRA", "VTI", 12.0, None), position("Joint", "BND", 1000.0, Some(80000.0))], positions);
+	}
+
+	#[test]
+	fn replace_removes_lots_left_out_of_imported_accounts() {
+		let lots = vec![lot(1, "IRA", "VTI", 10.0), lot(2, "IRA", "BND", 5.0), lot(3, "Joint", "BND", 7.0)];
+		let members = vec!["VTI".to_string(), "BND".to_string()];
+		let positions = vec![position("IRA", "VTI", 12.0, None), position("IRA", "GLD", 1.0, None)];
+		let merge = super::plan_import(&lots, &SquadAnnex::default(), &members, &positions, Mode::Merge, today());
+		let add = LotChange::Add { symbol: "VTI".to_string(), account: "IRA".to_string(), shares: 2.0, cost: None };
+		assert_eq!(vec![add.clone()], merge.changes);
+		assert_eq!(vec!["GLD".to_string()], merge.unknown);
+		let replace = super::plan_import(&lots, &SquadAnnex::default(), &members, &positions, Mode::Replace, today());
+		let remove = LotChange::Remove { lot_id: 2, symbol: "BND".to_string(), account: "IRA".to_string(), shares: 5.0 };
+		assert_eq!(vec![add, remove], replace.changes);
+	}
+
+	fn two_lots() -> (Vec<Lot>, SquadAnnex) {
+		let lots = vec![lot(1, "IRA", "VTI", 5.0), lot(2, "IRA", "VTI", 5.0)];
+		let mut annex = SquadAnnex::default();
+		annex.lots.insert(1, LotBasis { cost: Some(150.0), acquired: Some(date(2020, 6, 1)) });
+		annex.lots.insert(2, LotBasis { cost: Some(50.0), acquired: Some(date(2018, 1, 1)) });
+		(lots, annex)
+	}
+
+	#[test]
+	fn fewer_shares_trim_the_oldest_lots_and_keep_their_basis() {
+		let (lots, annex) = two_lots();
+		let members = vec!["VTI".to_string()];
+		let import = super::plan_import(&lots, &annex, &members, &[position("IRA", "VTI", 3.0, None)], Mode::Merge, today());
+		let remove = LotChange::Remove { lot_id: 2, symbol: "VTI".to_string(), account: "IRA".to_string(), shares: 5.0 };
+		let basis = Some(LotBasis { cost: Some(90.0), acquired: Some(date(2020, 6, 1)) });
+		let update = LotChange::Update { lot_id: 1, symbol: "VTI".to_string(), account: "IRA".to_string(), old_shares: 5.0, shares: 3.0, basis };
+		assert_eq!(vec![remove, update], import.changes);
+		assert!(import.conflicts.is_empty());
+	}
+
+	#[test]
+	fn zero_positions_add_nothing_and_negative_ones_are_conflicts() {
+		let (lots, annex) = two_lots();
+		let members = vec!["VTI".to_string(), "BND".to_string()];
+		let positions = [position("IRA", "BND", 0.0, None), position("IRA", "VTI", -2.0, None)];
+		let import = super::plan_import(&lots, &annex, &members, &positions, Mode::Merge, today());
+		assert!(import.changes.is_empty());
+		assert_eq!(vec!["IRA VTI: shares must be 0 or more, not -2".to_string()], import.conflicts);
+	}
+
+	#[test]
+	fn costs_that_do_not_split_among_lots_are_conflicts() {
+		let (lots, annex) = two_lots();
+		let members = vec!["VTI".to_string()];
+		let positions = [position("IRA", "VTI", 10.0, Some(200.0)), position("IRA", "VTI", 10.0, Some(250.0)), position("IRA", "VTI", 12.0, Some(240.0))];
+		let plan = |position: &Position| super::plan_import(&lots, &annex, &members, std::slice::from_ref(position), Mode::Merge, today());
+		assert_eq!(LotImport::default(), plan(&positions[0]));
+		let conflict = plan(&positions[1]);
+		assert!(conflict.changes.is_empty());
+		assert_eq!(vec!["IRA VTI: cost 250 does not match 200 in 2 lot(s)".to_string()], conflict.conflicts);
+		let add = LotChange::Add { symbol: "VTI".to_string(), account: "IRA".to_string(), shares: 2.0, cost: Some(40.0) };
+		assert_eq!(vec![add], plan(&positions[2]).changes);
+	}
+}
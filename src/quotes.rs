@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chad_core::core::Squad;
+use serde_json::Value;
+
+use crate::csv;
+
+pub const QUOTES_FILE: &str = "quotes.csv";
+pub const QUOTES_CACHE_FILE: &str = "quotes.json";
+
+/// Somewhere trove can ask for current prices.
+pub trait QuoteSource: Debug + Send + Sync {
+	fn quotes(&self, symbols: &[String]) -> Result<Vec<Quote>, String>;
+}
+
+/// Quotes from a local cache file, either json or csv.
+#[derive(Clone, Debug)]
+pub struct FileQuotes {
+	pub path: PathBuf,
+}
+
+impl FileQuotes {
+	/// Prefers the json cache in a directory and falls back to the csv.
+	pub fn in_dir(dir: &Path) -> Self {
+		let json_path = dir.join(QUOTES_CACHE_FILE);
+		let path = if json_path.exists() { json_path } else { dir.join(QUOTES_FILE) };
+		FileQuotes { path }
+	}
+}
+
+impl QuoteSource for FileQuotes {
+	fn quotes(&self, symbols: &[String]) -> Result<Vec<Quote>, String> {
+		let text = fs::read_to_string(&self.path).map_err(|e| format!("Cannot read {}: {}", self.path.display(), e))?;
+		let is_json = self.path.extension().map(|it| it == "json").unwrap_or(false);
+		let quotes = if is_json { parse_json(&text)? } else { parse_csv(&text)? };
+		Ok(quotes.into_iter().filter(|it| symbols.contains(&it.symbol)).collect())
+	}
+}
+
+/// Returns a copy of `prices` with every symbol the source could quote
+/// replaced by its quoted price.
+pub fn refresh_prices(source: &dyn QuoteSource, prices: &HashMap<String, f64>) -> Result<HashMap<String, f64>, String> {
+	let symbols = prices.keys().cloned().collect::<Vec<_>>();
+	let mut refreshed = prices.to_owned();
+	for quote in source.quotes(&symbols)? {
+		if let Some(price) = refreshed.get_mut(&quote.symbol) {
+			*price = quote.price;
+		}
+	}
+	Ok(refreshed)
+}
+
+/// Reads quotes from either a json object of symbol to price or a json
+/// array of objects with symbol, price and an optional date.
+pub fn parse_json(text: &str) -> Result<Vec<Quote>, String> {
+	let value = serde_json::from_str::<Value>(text).map_err(|e| e.to_string())?;
+	match value {
+		Value::Object(map) => map.iter().map(|(symbol, price)| {
+			let price = price.as_f64().ok_or_else(|| format!("Bad price for {}", symbol))?;
+			Ok(Quote { symbol: symbol.to_uppercase(), price, date: None })
+		}).collect(),
+		Value::Array(items) => items.iter().map(|item| {
+			let symbol = item["symbol"].as_str().ok_or_else(|| "Quote without symbol".to_string())?;
+			let price = item["price"].as_f64().ok_or_else(|| format!("Bad price for {}", symbol))?;
+			let date = item["date"].as_str().map(|it| it.to_string());
+			Ok(Quote { symbol: symbol.to_uppercase(), price, date })
+		}).collect(),
+		_ => Err("Expected a json object or array of quotes".to_string()),
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quote {
+	pub symbol: String,
+	pub price: f64,
+	pub date: Option<String>,
+}
+
+/// Reads quotes from csv rows of symbol, price and an optional date.
+/// Blank lines, #-comments and a header row whose first field is "symbol"
+/// are skipped.
+pub fn parse_csv(text: &str) -> Result<Vec<Quote>, String> {
+	let mut quotes = Vec::new();
+	let mut is_first_row = true;
+	for (index, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let fields = csv::split_line(line);
+		let is_header = is_first_row && fields[0].eq_ignore_ascii_case("symbol");
+		is_first_row = false;
+		if is_header {
+			continue;
+		}
+		if fields.len() < 2 || fields[0].is_empty() {
+			return Err(format!("Line {}: expected symbol,price[,date]", index + 1));
+		}
+		let price = csv::parse_number(&fields[1])
+			.ok_or_else(|| format!("Line {}: bad price {:?}", index + 1, fields[1]))?;
+		let date = fields.get(2).filter(|it| !it.is_empty()).cloned();
+		quotes.push(Quote { symbol: fields[0].to_uppercase(), price, date });
+	}
+	Ok(quotes)
+}
+
+#[derive(Clone, Debug)]
+pub struct PriceChange {
+	pub squad_id: u64,
+	pub squad_name: String,
+	pub symbol: String,
+	pub old_price: f64,
+	pub new_price: f64,
+	/// The date of the quote when the source gave one.
+	pub date: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PriceImport {
+	pub changes: Vec<PriceChange>,
+	pub unknown: Vec<String>,
+	/// Symbols quoted more than once. Their prices are left alone.
+	pub conflicts: Vec<String>,
+}
+
+/// Matches quotes against the prices of every squad. Quotes that match no
+/// squad are reported as unknown and prices that would not move are left out.
+pub fn plan_import(squads: &[Squad], quotes: &[Quote]) -> PriceImport {
+	let mut changes = Vec::new();
+	let mut unknown = Vec::new();
+	let mut conflicts = Vec::new();
+	let mut seen = HashSet::new();
+	for quote in quotes {
+		if !seen.insert(quote.symbol.to_owned()) {
+			continue;
+		}
+		let count = quotes.iter().filter(|it| it.symbol == quote.symbol).count();
+		if count > 1 {
+			conflicts.push(format!("{} has {} quotes", quote.symbol, count));
+			continue;
+		}
+		let mut known = false;
+		for squad in squads {
+			if let Some(old_price) = squad.prices.get(&quote.symbol) {
+				known = true;
+				if *old_price != quote.price {
+					changes.push(PriceChange {
+						squad_id: squad.id,
+						squad_name: squad.name.to_owned(),
+						symbol: quote.symbol.to_owned(),
+						old_price: *old_price,
+						new_price: quote.price,
+						date: quote.date.to_owned(),
+					});
+				}
+			}
+		}
+		if !known {
+			unknown.push(quote.symbol.to_owned());
+		}
+	}
+	PriceImport { changes, unknown, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::{Quote, QuoteSource};
+
+	fn quote(symbol: &str, price: f64) -> Quote {
+		Quote { symbol: symbol.to_string(), price, date: None }
+	}
+
+	#[derive(Debug, Default)]
+	struct MockQuotes {
+		prices: HashMap<String, f64>,
+	}
+
+	impl QuoteSource for MockQuotes {
+		fn quotes(&self, symbols: &[String]) -> Result<Vec<Quote>, String> {
+			let quotes = symbols.iter()
+				.filter_map(|symbol| self.prices.get(symbol).map(|price| Quote { symbol: symbol.to_owned(), price: *price, date: None }))
+				.collect();
+			Ok(quotes)
+		}
+	}
+
+	#[test]
+	fn refresh_prices_keeps_unquoted_symbols() {
+		let source = MockQuotes { prices: vec![("VTI".to_string(), 210.0), ("GLD".to_string(), 170.0)].into_iter().collect() };
+		let prices = vec![("VTI".to_string(), 200.0), ("BND".to_string(), 84.0)].into_iter().collect::<HashMap<_, _>>();
+		let refreshed = super::refresh_prices(&source, &prices).unwrap();
+		let expected = vec![("VTI".to_string(), 210.0), ("BND".to_string(), 84.0)].into_iter().collect::<HashMap<_, _>>();
+		assert_eq!(expected, refreshed);
+	}
+
+	#[test]
+	fn plan_import_reports_symbols_quoted_twice() {
+		let import = super::plan_import(&[], &[quote("VTI", 201.5), quote("BND", 84.0), quote("VTI", 203.0)]);
+		assert!(import.changes.is_empty());
+		assert_eq!(vec!["BND".to_string()], import.unknown);
+		assert_eq!(vec!["VTI has 2 quotes".to_string()], import.conflicts);
+	}
+
+	#[test]
+	fn parse_json_reads_objects_and_arrays() {
+		let object = super::parse_json(r#"{"vti": 201.5}"#).unwrap();
+		assert_eq!(vec![Quote { symbol: "VTI".into(), price: 201.5, date: None }], object);
+		let array = super::parse_json(r#"[{"symbol": "BND", "price": 84.25, "date": "2020-06-01"}]"#).unwrap();
+		assert_eq!(vec![Quote { symbol: "BND".into(), price: 84.25, date: Some("2020-06-01".into()) }], array);
+	}
+
+	#[test]
+	fn parse_csv_skips_header_and_comments() {
+		let text = "symbol,price,date\n# morning quotes\n\nvti, 201.5, 2020-06-01\n\"BND\",$84.25\n";
+		let quotes = super::parse_csv(text).unwrap();
+		assert_eq!(vec![
+			Quote { symbol: "VTI".into(), price: 201.5, date: Some("2020-06-01".into()) },
+			Quote { symbol: "BND".into(), price: 84.25, date: None },
+		], quotes);
+	}
+
+	#[test]
+	fn parse_csv_reports_bad_price() {
+		let text = "VTI,201.5\nBND,lots\n";
+		assert_eq!(Err("Line 2: bad price \"lots\"".to_string()), super::parse_csv(text));
+	}
+
+	#[test]
+	fn parse_csv_reports_bad_first_row_that_is_not_a_header() {
+		let text = "# no header\nVTI,n/a\nBND,84.25\n";
+		assert_eq!(Err("Line 2: bad price \"n/a\"".to_string()), super::parse_csv(text));
+	}
+
+	#[test]
+	fn parse_csv_keeps_commas_inside_quotes() {
+		let text = "Symbol,Price,Date\n\"BRK.B\",\"$1,201.50\",2020-06-01\n";
+		let quotes = super::parse_csv(text).unwrap();
+		assert_eq!(vec![Quote { symbol: "BRK.B".into(), price: 1201.5, date: Some("2020-06-01".into()) }], quotes);
+	}
+}
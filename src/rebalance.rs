@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use chad_core::core::Lot;
+use chrono::{Local, NaiveDate};
+use yui::{AfterFlow, ArcYard, Cling, Confine, Create, Flow, Pack, SenderLink, yard};
+use yui::palette::StrokeColor;
+use yui::yard::ButtonState;
+
+use crate::{compute, render, sprint, YardId};
+use crate::compute::{Holding, LotPolicy, LotSale, Mode, Plan, Side};
+use crate::vault::{SquadAnnex, Vault};
+
+#[derive(Clone, Debug)]
+pub struct State {
+	holdings: Vec<Holding>,
+	cash: BTreeMap<String, f64>,
+	lots: Vec<Lot>,
+	annex: SquadAnnex,
+	today: NaiveDate,
+	policy: LotPolicy,
+	plan: Plan,
+	sales: Vec<LotSale>,
+}
+
+impl State {
+	/// Plans again after a change of mode or lot policy.
+	fn replan(&mut self, mode: Mode) {
+		let mut holdings = self.holdings.clone();
+		for holding in &mut holdings {
+			holding.sellable = compute::sellable(&holding.symbol, &self.lots, &self.annex, self.policy, self.today);
+		}
+		self.plan = compute::plan(&holdings, &self.cash, mode);
+		self.sales = compute::lot_sales(&self.plan.orders, &self.lots, &self.annex, self.policy, self.today);
+	}
+}
+
+pub enum Action {
+	Close,
+	ToggleMode,
+	NextPolicy,
+}
+
+pub struct Spark {
+	pub vault: Vault,
+	pub squad_id: u64,
+}
+
+impl yui::Spark for Spark {
+	type State = State;
+	type Action = Action;
+	type Report = u64;
+
+	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
+		let squad = self.vault.squad(self.squad_id).expect("Squad exists");
+		let annex = self.vault.squad_annex(squad.id);
+		let holdings = compute::holdings(&squad, &annex);
+		let cash = compute::squad_cash(&squad, &annex);
+		let plan = compute::plan(&holdings, &cash, Mode::SellAndBuy);
+		let mut state = State {
+			holdings,
+			cash,
+			lots: squad.lots,
+			annex,
+			today: Local::now().naive_local().date(),
+			policy: LotPolicy::Fifo,
+			plan,
+			sales: Vec::new(),
+		};
+		state.replan(Mode::SellAndBuy);
+		state
+	}
+
+	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
+		match action {
+			Action::Close => AfterFlow::Close(None),
+			Action::ToggleMode => {
+				let mut state = ctx.state().clone();
+				let mode = match state.plan.mode {
+					Mode::SellAndBuy => Mode::CashOnly,
+					Mode::CashOnly => Mode::SellAndBuy,
+				};
+				state.replan(mode);
+				AfterFlow::Revise(state)
+			}
+			Action::NextPolicy => {
+				let mut state = ctx.state().clone();
+				state.policy = match state.policy {
+					LotPolicy::Fifo => LotPolicy::Lifo,
+					LotPolicy::Lifo => LotPolicy::HighestCost,
+					LotPolicy::HighestCost => LotPolicy::LossesFirst,
+					LotPolicy::LossesFirst => LotPolicy::LongTermOnly,
+					LotPolicy::LongTermOnly => LotPolicy::Fifo,
+				};
+				state.replan(state.plan.mode);
+				AfterFlow::Revise(state)
+			}
+		}
+	}
+
+	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let plan = &state.plan;
+		let mut items = Vec::new();
+		items.push((1, yard::label("Orders", StrokeColor::BodyOnBackground, Cling::Left)));
+		if plan.orders.is_empty() {
+			items.push((1, yard::label("No trades needed", StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		for order in &plan.orders {
+			let side = match order.side {
+				Side::Buy => "Buy",
+				Side::Sell => "Sell",
+			};
+			let text = format!(
+				"{} {} {} in {} @ {} = {}",
+				side,
+				order.shares,
+				order.symbol,
+				account_name(&order.account),
+				sprint::amount(order.price),
+				sprint::amount(order.amount()),
+			);
+			items.push((1, yard::label(text, StrokeColor::BodyOnBackground, Cling::Left)));
+			let sales = state.sales.iter().filter(|it| it.symbol == order.symbol && it.account == order.account);
+			for sale in sales {
+				let acquired = sale.acquired.map(|it| it.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "undated".to_string());
+				let gain = match sale.gain {
+					None => "no cost basis".to_string(),
+					Some(gain) if gain < 0.0 => format!("loss {}", sprint::amount(-gain)),
+					Some(gain) => format!("gain {}", sprint::amount(gain)),
+				};
+				let text = format!("  {} sh from lot {}, {}", sprint::amount_prefix(sale.shares, ""), acquired, gain);
+				items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+			}
+		}
+		if !state.sales.is_empty() {
+			let gains = compute::realized_gains(&state.sales, state.today);
+			let mut text = format!(
+				"Estimated realized gain: {} short-term, {} long-term",
+				sprint::amount(gains.short_term),
+				sprint::amount(gains.long_term),
+			);
+			if gains.undated != 0.0 {
+				text.push_str(&format!(", {} undated", sprint::amount(gains.undated)));
+			}
+			if gains.unknown_count > 0 {
+				text.push_str(&format!(" ({} lot(s) without cost)", gains.unknown_count));
+			}
+			items.push((1, yard::label(text, StrokeColor::BodyOnBackground, Cling::Left)));
+		}
+		items.push((1, yard::empty()));
+		items.push((1, yard::label("After trades", StrokeColor::BodyOnBackground, Cling::Left)));
+		for holding in &plan.after {
+			let drift_amount = holding.drift_amount(plan.total);
+			let drift = if drift_amount.is_sign_positive() {
+				format!("Over {}", sprint::amount(drift_amount.abs()))
+			} else {
+				format!("Under {}", sprint::amount(drift_amount.abs()))
+			};
+			let text = format!("{} {} sh, {}", holding.symbol, sprint::amount_prefix(holding.shares(), ""), drift);
+			items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		let leftover = format!("Unspent left {}", sprint::amount(plan.leftover()));
+		items.push((1, yard::label(leftover, StrokeColor::CommentOnBackground, Cling::Left)));
+		for (account, cash) in plan.cash.iter().filter(|(_, cash)| **cash > 0.0) {
+			let text = format!("  {} in {}", sprint::amount(*cash), account_name(account));
+			items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		let overweight = plan.overweight();
+		if !overweight.is_empty() {
+			let symbols = overweight.iter().map(|it| it.symbol.as_str()).collect::<Vec<_>>();
+			let text = format!("Still overweight: {}", symbols.join(", "));
+			items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		let mode_text = match plan.mode {
+			Mode::SellAndBuy => "Mode: Sell and buy",
+			Mode::CashOnly => "Mode: Cash only",
+		};
+		let mode_button = yard::button(mode_text, ButtonState::enabled(link.map(|_| Action::ToggleMode)));
+		let policy_text = match state.policy {
+			LotPolicy::Fifo => "Lots: FIFO",
+			LotPolicy::Lifo => "Lots: LIFO",
+			LotPolicy::HighestCost => "Lots: Highest cost",
+			LotPolicy::LossesFirst => "Lots: Losses first",
+			LotPolicy::LongTermOnly => "Lots: Long-term only",
+		};
+		let policy_button = yard::button(policy_text, ButtonState::enabled(link.map(|_| Action::NextPolicy)));
+		let buttons = yard::empty()
+			.pack_left(policy_text.len() as i32 + 6, policy_button)
+			.pack_left(mode_text.len() as i32 + 7, mode_button.confine_width(mode_text.len() as i32 + 6, Cling::LeftTop));
+		let content = yard::list(YardId::RebalanceList.as_i32(), 0, items)
+			.pack_top(4, buttons.confine_height(3, Cling::LeftTop));
+		let yard = render::dialog(
+			"Rebalance",
+			link.map(|_| Action::Close),
+			ButtonState::disabled(),
+			None,
+			content,
+		);
+		Some(yard)
+	}
+}
+
+fn account_name(account: &str) -> &str {
+	if account.is_empty() { "any account" } else { account }
+}
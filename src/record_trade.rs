@@ -0,0 +1,147 @@
+use chad_core::core::Squad;
+use chrono::{Local, NaiveDate};
+use stringedit::{StringEdit, Validity};
+use yui::{AfterFlow, ArcYard, Cling, Confine, Create, Flow, Pack, SenderLink, StringEditAction, yard};
+use yui::palette::StrokeColor;
+use yui::yard::ButtonState;
+
+use crate::{render, YardId};
+use crate::compute::Side;
+use crate::vault::{Trade, Vault};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Clone, Debug)]
+pub struct State {
+	symbol: String,
+	side: Side,
+	account_edit: StringEdit,
+	shares_edit: StringEdit,
+	price_edit: StringEdit,
+	date_edit: StringEdit,
+	error: Option<String>,
+}
+
+impl State {
+	fn trade(&self) -> Result<Trade, &'static str> {
+		let text = |edit: &StringEdit| edit.chars.iter().cloned().collect::<String>().trim().to_owned();
+		let account = text(&self.account_edit);
+		if account.is_empty() {
+			return Err("Account is required");
+		}
+		let shares = text(&self.shares_edit).parse::<f64>().map_err(|_| "Shares must be a number")?;
+		let price = text(&self.price_edit).trim_start_matches('$').parse::<f64>().map_err(|_| "Price must be a number")?;
+		let date = NaiveDate::parse_from_str(&text(&self.date_edit), DATE_FORMAT).map_err(|_| "Date must be like 2020-01-31")?;
+		let trade = Trade { side: self.side, symbol: self.symbol.to_owned(), account, shares, price, date };
+		trade.check()?;
+		Ok(trade)
+	}
+}
+
+pub enum Action {
+	Close,
+	ToggleSide,
+	EditAccount(StringEditAction),
+	EditShares(StringEditAction),
+	EditPrice(StringEditAction),
+	EditDate(StringEditAction),
+	Submit,
+}
+
+pub struct Spark {
+	pub vault: Vault,
+	pub squad_id: u64,
+	pub symbol: String,
+}
+
+impl Spark {
+	fn squad(&self) -> Squad {
+		self.vault.squad(self.squad_id).expect("Squad exists")
+	}
+}
+
+fn edit(text: String, validity: Validity) -> StringEdit {
+	let cursor_pos = text.chars().count();
+	StringEdit::new(text, cursor_pos, validity)
+}
+
+impl yui::Spark for Spark {
+	type State = State;
+	type Action = Action;
+	type Report = (u64, String);
+
+	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
+		let squad = self.squad();
+		let account = squad.lots.iter().find(|it| it.symbol == self.symbol).map(|it| it.account.to_owned()).unwrap_or_default();
+		let price = squad.prices.get(&self.symbol).map(|it| format!("{}", it)).unwrap_or_default();
+		let date = Local::now().naive_local().date().format(DATE_FORMAT).to_string();
+		State {
+			symbol: self.symbol.to_owned(),
+			side: Side::Buy,
+			account_edit: edit(account, Validity::NotEmpty),
+			shares_edit: StringEdit::empty(Validity::Double),
+			price_edit: edit(price, Validity::Double),
+			date_edit: edit(date, Validity::NotEmpty),
+			error: None,
+		}
+	}
+
+	fn flow(&self, action: Self::Action, ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
+		let mut state = ctx.state().clone();
+		state.error = None;
+		match action {
+			Action::Close => return AfterFlow::Close(None),
+			Action::ToggleSide => state.side = match state.side {
+				Side::Buy => Side::Sell,
+				Side::Sell => Side::Buy,
+			},
+			Action::EditAccount(action) => state.account_edit = state.account_edit.edit(action),
+			Action::EditShares(action) => state.shares_edit = state.shares_edit.edit(action),
+			Action::EditPrice(action) => state.price_edit = state.price_edit.edit(action),
+			Action::EditDate(action) => state.date_edit = state.date_edit.edit(action),
+			Action::Submit => match state.trade() {
+				Err(_) => return AfterFlow::Ignore,
+				Ok(trade) => match self.vault.record_trade(&self.squad(), trade) {
+					Ok(()) => return AfterFlow::Close(Some((self.squad_id, self.symbol.to_owned()))),
+					Err(message) => state.error = Some(message),
+				},
+			},
+		}
+		AfterFlow::Revise(state)
+	}
+
+	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let side_text = match state.side {
+			Side::Buy => "Side: Buy",
+			Side::Sell => "Side: Sell",
+		};
+		let side_button = yard::button(side_text, ButtonState::enabled(link.map(|_| Action::ToggleSide)));
+		let mut fields = vec![
+			side_button.confine_width(side_text.len() as i32 + 6, Cling::LeftTop),
+			yard::textfield(YardId::TradeAccountEdit.as_i32(), "Account", state.account_edit.clone(), link.map(Action::EditAccount)),
+			yard::textfield(YardId::TradeSharesEdit.as_i32(), "Shares", state.shares_edit.clone(), link.map(Action::EditShares)),
+			yard::textfield(YardId::TradePriceEdit.as_i32(), "Price", state.price_edit.clone(), link.map(Action::EditPrice)),
+			yard::textfield(YardId::TradeDateEdit.as_i32(), "Date (YYYY-MM-DD)", state.date_edit.clone(), link.map(Action::EditDate)),
+		];
+		let trade = state.trade();
+		let note = match (&state.error, &trade) {
+			(Some(error), _) => error.to_owned(),
+			(None, Err(problem)) => problem.to_string(),
+			(None, Ok(_)) => match state.side {
+				Side::Buy => "Adds a lot and spends unspent cash".to_string(),
+				Side::Sell => "Sells from the oldest lots first and adds to unspent cash".to_string(),
+			},
+		};
+		fields.push(yard::label(note, StrokeColor::CommentOnBackground, Cling::Left));
+		let content = yard::trellis(3, 1, Cling::Top, fields)
+			.pack_top(2, yard::label(&state.symbol, StrokeColor::BodyOnBackground, Cling::LeftTop));
+		let yard = render::dialog(
+			"Record Trade",
+			link.map(|_| Action::Close),
+			if trade.is_ok() { ButtonState::enabled(link.map(|_| Action::Submit)) } else { ButtonState::disabled() },
+			None,
+			content,
+		);
+		Some(yard)
+	}
+}
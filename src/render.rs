@@ -1,30 +1,86 @@
-use chad_core::core::{DriftReport, Lot, Squad, SquadMember};
-use yui::{ArcYard, Before, Cling, Confine, Pack, Padding, SenderLink, yard};
+use std::collections::BTreeMap;
+use std::io;
+
+use chad_core::core::{Lot, Squad, SquadMember};
+use chrono::{Local, NaiveDate};
+use stringedit::{StringEdit, Validity};
+use yui::{ArcYard, Before, Cling, Confine, Pack, Padding, SenderLink, StringEditAction, yard};
 use yui::palette::{FillColor, StrokeColor};
 use yui::yard::{ButtonState, Pressable};
 
-use crate::{sprint, YardId};
+use crate::{compute, sprint, YardId};
+use crate::compute::{Drift, Side, Term};
 use crate::sprint::amount_prefix;
+use crate::vault::{Band, LotBasis, SquadAnnex};
 use yui::palette::FillGrade::Plain;
 
-pub fn lot_summary(lot: &Lot, select_link: SenderLink<()>) -> (u8, ArcYard) {
-	let text = format!("{} shares in {} account", amount_prefix(lot.shares, ""), &lot.account);
+pub fn lot_summary(lot: &Lot, basis: &LotBasis, price: f64, today: NaiveDate, select_link: SenderLink<()>) -> (u8, ArcYard) {
+	let mut text = format!("{} shares in {} account", amount_prefix(lot.shares, ""), &lot.account);
+	if let Some(cost) = basis.cost {
+		text.push_str(&format!(", cost {}", sprint::amount(cost)));
+		if lot.shares > 0.0 {
+			text.push_str(&format!(" ({}/sh)", sprint::amount(cost / lot.shares)));
+		}
+	}
+	if let Some(acquired) = basis.acquired {
+		text.push_str(&format!(", acquired {}", acquired.format("%Y-%m-%d")));
+	}
+	if let Some(gain) = compute::lot_gain(lot, basis, price) {
+		let term = match basis.acquired.map(|it| compute::term(it, today)) {
+			None => "",
+			Some(Term::Short) => " short-term",
+			Some(Term::Long) => " long-term",
+		};
+		let (word, gain) = if gain < 0.0 { ("loss", -gain) } else { ("gain", gain) };
+		text.push_str(&format!(", {}{} {}", word, term, sprint::amount(gain)));
+	}
 	let yard = yard::label(&text, StrokeColor::BodyOnBackground, Cling::Left)
 		.pressable(select_link.map(|_| ()));
 	(1, yard)
 }
 
-pub fn member_view(member: &SquadMember, squad: &Squad, lot_link: SenderLink<(u64, String, Option<u64>)>) -> ArcYard {
+pub fn member_view(member: &SquadMember, squad: &Squad, annex: &SquadAnnex, lot_link: SenderLink<(u64, String, Option<u64>)>, edit_link: SenderLink<(u64, String)>, trade_link: SenderLink<(u64, String)>) -> ArcYard {
 	let lots = squad.lots.iter().filter(|it| it.symbol == member.symbol).collect::<Vec<_>>();
+	let today = Local::now().naive_local().date();
 	let header = {
 		let title = yard::title(&member.symbol, StrokeColor::BodyOnPrimary, Cling::Left);
 		let shares = lots.iter().map(|it| it.shares).sum::<f64>();
 		let shares_label = yard::label(format!("Shares: {}", sprint::amount_prefix(shares, "")), StrokeColor::BodyOnPrimary, Cling::LeftBottom);
-		let market_value = shares * squad.prices[&member.symbol];
-		let market_label = yard::label(format!("Market value: {}", sprint::amount(market_value)), StrokeColor::BodyOnPrimary, Cling::Left);
+		let price = squad.prices[&member.symbol];
+		let market_value = shares * price;
+		let market_label = {
+			let mut text = format!("Market value: {}", sprint::amount(market_value));
+			let gains = compute::gains(&lots, annex, price, today);
+			if gains.unknown_count < lots.len() {
+				text.push_str(&format!(
+					"   Unrealized: {} short-term, {} long-term",
+					sprint::amount(gains.short_term),
+					sprint::amount(gains.long_term),
+				));
+				if gains.undated != 0.0 {
+					text.push_str(&format!(", {} undated", sprint::amount(gains.undated)));
+				}
+				if gains.unknown_count > 0 {
+					text.push_str(&format!(" ({} lot(s) without cost)", gains.unknown_count));
+				}
+			}
+			yard::label(text, StrokeColor::BodyOnPrimary, Cling::Left)
+		};
+		let accounts_label = {
+			let mut accounts = BTreeMap::new();
+			for lot in &lots {
+				*accounts.entry(lot.account.as_str()).or_insert(0.0) += lot.shares;
+			}
+			let text = accounts.iter()
+				.map(|(account, shares)| format!("{} {} sh", account, sprint::amount_prefix(*shares, "")))
+				.collect::<Vec<_>>()
+				.join(", ");
+			yard::label(format!("Accounts: {}", text), StrokeColor::BodyOnPrimary, Cling::Left)
+		};
 		let front = title
 			.pack_bottom(2, shares_label)
 			.pack_bottom(1, market_label)
+			.pack_bottom(1, accounts_label)
 			.pad(1);
 		front.before(yard::fill(FillColor::Primary, Plain))
 	};
@@ -34,7 +90,7 @@ pub fn member_view(member: &SquadMember, squad: &Squad, lot_link: SenderLink<(u6
 			yard::label("No Lots", StrokeColor::CommentOnBackground, Cling::Center)
 		} else {
 			let lot_items = lots.into_iter()
-				.map(|it| lot_summary(it, lot_link.map({
+				.map(|it| lot_summary(it, &annex.lots.get(&it.id).cloned().unwrap_or_default(), squad.prices[&member.symbol], today, lot_link.map({
 					let path = (it.squad_id, it.symbol.to_string(), Some(it.id));
 					move |_| path.clone()
 				})))
@@ -46,15 +102,52 @@ pub fn member_view(member: &SquadMember, squad: &Squad, lot_link: SenderLink<(u6
 			let path = (member.squad_id, member.symbol.clone(), None);
 			move |_| path.clone()
 		})));
-		lot_list.pack_bottom(3, add_button.confine(13, 3, Cling::Top))
+		let edit_button = yard::button("Edit Member", ButtonState::enabled(edit_link.map({
+			let path = (member.squad_id, member.symbol.clone());
+			move |_| path.clone()
+		})));
+		let trade_button = yard::button("Record Trade", ButtonState::enabled(trade_link.map({
+			let path = (member.squad_id, member.symbol.clone());
+			move |_| path.clone()
+		})));
+		let buttons = yard::empty()
+			.pack_left(17, edit_button)
+			.pack_left(18, trade_button.confine_width(17, Cling::Left))
+			.pack_left(14, add_button.confine_width(13, Cling::Left));
+		let trades = annex.trades.iter().rev().filter(|it| it.symbol == member.symbol).take(5).collect::<Vec<_>>();
+		let lot_list = if trades.is_empty() {
+			lot_list
+		} else {
+			let trades_label = yard::label("Recent trades", StrokeColor::BodyOnBackground, Cling::Left);
+			let trade_lines = trades.iter().fold(trades_label, |yard, trade| {
+				let side = match trade.side {
+					Side::Buy => "Buy",
+					Side::Sell => "Sell",
+				};
+				let text = format!(
+					"{} {} {} sh in {} @ {}",
+					trade.date.format("%Y-%m-%d"),
+					side,
+					sprint::amount_prefix(trade.shares, ""),
+					trade.account,
+					sprint::amount(trade.price),
+				);
+				yard.pack_bottom(1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left))
+			});
+			lot_list.pack_bottom(trades.len() as i32 + 2, trade_lines.pack_top(1, yard::empty()))
+		};
+		lot_list.pack_bottom(3, buttons)
 	};
-	content.pad(1).pack_top(7, header)
+	content.pad(1).pack_top(8, header)
 }
 
-pub fn drift_summary(report: &DriftReport, select_link: SenderLink<(u64, String)>) -> (u8, ArcYard) {
+pub fn drift_summary(report: &Drift, select_link: SenderLink<(u64, String)>, move_link: SenderLink<(u64, String, i32)>) -> (u8, ArcYard) {
 	let drift_amount = report.drift_amount();
 	let left = {
-		let symbol = format!("{}", report.symbol());
+		let symbol = match report.out_of_band {
+			Some(true) => format!("{} !", report.symbol()),
+			_ => report.symbol().to_string(),
+		};
 		let rank = format!("R{}({}%)", report.rank, sprint::amount_prefix(report.target_portion * 100.0, ""));
 		yard::label(symbol, StrokeColor::BodyOnBackground, Cling::LeftBottom)
 			.pack_bottom(
@@ -62,7 +155,9 @@ pub fn drift_summary(report: &DriftReport, select_link: SenderLink<(u64, String)
 				yard::label(rank, StrokeColor::CommentOnBackground, Cling::LeftTop),
 			)
 	};
-	let right = {
+	let right = if report.out_of_band == Some(false) {
+		yard::label("In band", StrokeColor::CommentOnBackground, Cling::RightBottom).pack_bottom(1, yard::empty())
+	} else {
 		let relative_drift = {
 			if drift_amount.is_sign_positive() {
 				format!("{} {}", "Over", sprint::amount(drift_amount.abs()))
@@ -101,7 +196,7 @@ pub fn drift_summary(report: &DriftReport, select_link: SenderLink<(u64, String)
 			let bottom = yard::label(format!("\\- {}", sprint::amount(target_value)), StrokeColor::CommentOnBackground, Cling::RightTop);
 			(top, bottom)
 		} else {
-			let top = yard::label(format!("{}", sprint::amount(target_value)), StrokeColor::CommentOnBackground, Cling::RightTop);
+			let top = yard::label(sprint::amount(target_value), StrokeColor::CommentOnBackground, Cling::RightTop);
 			let bottom = yard::label(format!("{} -/", sprint::amount(market_value)), StrokeColor::BodyOnBackground, Cling::LeftBottom);
 			(top, bottom)
 		};
@@ -110,28 +205,76 @@ pub fn drift_summary(report: &DriftReport, select_link: SenderLink<(u64, String)
 	let content = center
 		.pack_left(12, left)
 		.pack_right(12, right);
+	let squad_id = report.member.squad_id;
+	let symbol = report.symbol().to_string();
+	let arrows = {
+		let arrow = |text: &str, offset: i32| yard::label(text, StrokeColor::CommentOnBackground, Cling::Center)
+			.pressable(move_link.map({
+				let symbol = symbol.clone();
+				move |_| (squad_id, symbol.clone(), offset)
+			}));
+		arrow("▲", -1).pack_bottom(2, arrow("▼", 1))
+	};
 	let cell = content.pad(1).pressable(select_link.map({
-		let squad_id = report.member.squad_id;
-		let symbol = report.symbol().to_string();
+		let symbol = symbol.clone();
 		move |_| (squad_id, symbol.clone())
-	})).confine_width(50, Cling::Custom { x: 0.1, y: 0.5 });
+	})).pack_right(3, arrows).confine_width(53, Cling::Custom { x: 0.1, y: 0.5 });
 	(4, cell)
 }
 
-pub fn squad(squad: &Squad, add_member_link: SenderLink<()>, view_member_link: SenderLink<(u64, String)>, set_unspent_link: SenderLink<(u64, Option<f64>)>) -> ArcYard {
+pub struct SquadLinks {
+	pub add_member: SenderLink<()>,
+	pub view_member: SenderLink<(u64, String)>,
+	pub move_member: SenderLink<(u64, String, i32)>,
+	pub set_unspent: SenderLink<u64>,
+	pub edit_squad: SenderLink<u64>,
+	pub duplicate_squad: SenderLink<u64>,
+	pub edit_prices: SenderLink<u64>,
+	pub edit_targets: SenderLink<u64>,
+	pub refresh_prices: SenderLink<()>,
+	pub rebalance: SenderLink<u64>,
+	pub view_history: SenderLink<u64>,
+	pub export_squad: SenderLink<u64>,
+}
+
+pub fn squad(squad: &Squad, annex: &SquadAnnex, links: SquadLinks) -> ArcYard {
 	let title = yard::title(&squad.name, StrokeColor::BodyOnPrimary, Cling::LeftBottom);
-	let header = title.pad(1).before(yard::fill(FillColor::Primary, Plain));
+	let header = {
+		let squad_id = squad.id;
+		let edit = yard::button("Edit", ButtonState::enabled(links.edit_squad.map(move |_| squad_id)));
+		let duplicate = yard::button("Duplicate", ButtonState::enabled(links.duplicate_squad.map(move |_| squad_id)));
+		let prices = yard::button("Prices", ButtonState::enabled(links.edit_prices.map(move |_| squad_id)));
+		let targets = yard::button("Targets", ButtonState::enabled(links.edit_targets.map(move |_| squad_id)));
+		let refresh = yard::button("Refresh", ButtonState::enabled(links.refresh_prices.map(|_| ())));
+		let rebalance = yard::button("Rebalance", ButtonState::enabled(links.rebalance.map(move |_| squad_id)));
+		let history = yard::button("History", ButtonState::enabled(links.view_history.map(move |_| squad_id)));
+		let export = yard::button("Export", ButtonState::enabled(links.export_squad.map(move |_| squad_id)));
+		title
+			.pack_right(12, prices)
+			.pack_right(13, targets)
+			.pack_right(13, refresh)
+			.pack_right(15, rebalance)
+			.pack_right(13, history)
+			.pack_right(12, export)
+			.pack_right(10, edit)
+			.pack_right(15, duplicate)
+			.pad(1)
+			.before(yard::fill(FillColor::Primary, Plain))
+	};
 	let content = {
 		let unspent = {
 			let label_text = "Unspent: ";
 			let label = yard::label(label_text, StrokeColor::BodyOnBackground, Cling::Left);
 			let button_text = sprint::amount(squad.unspent);
-			let button = yard::button(&button_text, ButtonState::default(set_unspent_link.map({
+			let button = yard::button(&button_text, ButtonState::default(links.set_unspent.map({
 				let squad_id = squad.id;
-				let unspent = if squad.unspent == 0.0 { None } else { Some(squad.unspent) };
-				move |_| (squad_id, unspent)
+				move |_| squad_id
 			})));
-			yard::empty()
+			let split = annex.cash.iter()
+				.map(|(account, cash)| format!("{} {}", account, sprint::amount(*cash)))
+				.collect::<Vec<_>>()
+				.join(", ");
+			yard::label(split, StrokeColor::CommentOnBackground, Cling::Left)
 				.pack_left(button_text.len() as i32 + 6, button)
 				.pack_left(label_text.len() as i32, label)
 		};
@@ -142,19 +285,19 @@ pub fn squad(squad: &Squad, add_member_link: SenderLink<()>, view_member_link: S
 			let list = if member_count == 0 {
 				yard::label("No members", StrokeColor::CommentOnBackground, Cling::Center)
 			} else {
-				let items = squad.drift_reports().iter().rev().map(|report| {
-					drift_summary(report, view_member_link.clone())
+				let items = compute::drifts(squad, annex).iter().map(|report| {
+					drift_summary(report, links.view_member.clone(), links.move_member.clone())
 				}).collect();
 				yard::list(YardId::SquadMembersList.as_i32(), 0, items)
 			};
-			let button = yard::button("Add Member", ButtonState::enabled(add_member_link.map(|_| ())));
+			let button = yard::button("Add Member", ButtonState::enabled(links.add_member.map(|_| ())));
 			list
 				.pack_top(1, label)
 				.pack_bottom(3, button)
 		};
 		members.pack_top(3, unspent)
 	}.pad(1);
-	content.pack_top(4, header)
+	content.pack_top(5, header)
 }
 
 pub fn dialog(title: &str, close_link: SenderLink<()>, submit_button_state: ButtonState, delete_link: Option<SenderLink<()>>, content: ArcYard) -> ArcYard {
@@ -173,4 +316,42 @@ pub fn dialog(title: &str, close_link: SenderLink<()>, submit_button_state: Butt
 	let content = content.pad(1).pack_left(LEFT_COLS, yard::empty());
 	content.pack_top(3, header)
 		.pack_bottom(4, footer.confine_height(3, Cling::Top).pad_cols(2).pack_left(LEFT_COLS, yard::empty()))
-}
\ No newline at end of file
+}
+
+/// The message a dialog shows when the vault could not save its edit.
+pub fn save_error(error: io::Error) -> String {
+	format!("Could not save: {}", error)
+}
+
+/// Edits for the limits of a band. An empty edit leaves its limit unset.
+pub fn band_edits(band: &Band) -> (StringEdit, StringEdit) {
+	let edit = |limit: Option<f64>| match limit {
+		None => StringEdit::empty(Validity::Double),
+		Some(limit) => {
+			let text = format!("{}", limit);
+			let cursor_pos = text.chars().count();
+			StringEdit::new(text, cursor_pos, Validity::Double)
+		}
+	};
+	(edit(band.absolute), edit(band.relative))
+}
+
+/// The band in a pair of edits or None while either edit is invalid.
+pub fn band_from_edits(absolute_edit: &StringEdit, relative_edit: &StringEdit) -> Option<Band> {
+	let limit = |edit: &StringEdit| -> Option<Option<f64>> {
+		let text = edit.chars.iter().cloned().collect::<String>();
+		if text.trim().is_empty() {
+			Some(None)
+		} else {
+			text.trim().parse::<f64>().ok().filter(|it| *it >= 0.0).map(Some)
+		}
+	};
+	Some(Band { absolute: limit(absolute_edit)?, relative: limit(relative_edit)? })
+}
+
+pub fn band_fields(absolute_edit: &StringEdit, relative_edit: &StringEdit, absolute_link: SenderLink<StringEditAction>, relative_link: SenderLink<StringEditAction>) -> Vec<ArcYard> {
+	vec![
+		yard::textfield(YardId::BandAbsoluteEdit.as_i32(), "Band (points of total)", absolute_edit.clone(), absolute_link),
+		yard::textfield(YardId::BandRelativeEdit.as_i32(), "Band (% of target)", relative_edit.clone(), relative_link),
+	]
+}
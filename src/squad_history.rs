@@ -0,0 +1,67 @@
+use yui::{AfterFlow, ArcYard, Cling, Create, Flow, SenderLink, yard};
+use yui::palette::StrokeColor;
+use yui::yard::ButtonState;
+
+use crate::{render, YardId};
+use crate::history::Change;
+use crate::vault::Vault;
+
+#[derive(Clone, Debug)]
+pub struct State {
+	name: String,
+	/// Changes newest first.
+	changes: Vec<Change>,
+}
+
+pub enum Action {
+	Close,
+}
+
+pub struct Spark {
+	pub vault: Vault,
+	pub squad_id: u64,
+}
+
+impl yui::Spark for Spark {
+	type State = State;
+	type Action = Action;
+	type Report = u64;
+
+	fn create(&self, _ctx: &Create<Self::Action, Self::Report>) -> Self::State {
+		let squad = self.vault.squad(self.squad_id).expect("Squad exists");
+		let mut changes = self.vault.history(squad.id);
+		changes.reverse();
+		State { name: squad.name, changes }
+	}
+
+	fn flow(&self, action: Self::Action, _ctx: &impl Flow<Self::State, Self::Action, Self::Report>) -> AfterFlow<Self::State, Self::Report> {
+		match action {
+			Action::Close => AfterFlow::Close(Some(self.squad_id)),
+		}
+	}
+
+	fn render(state: &Self::State, link: &SenderLink<Self::Action>) -> Option<ArcYard> {
+		let mut items = vec![(1, yard::label(&state.name, StrokeColor::BodyOnBackground, Cling::Left))];
+		if state.changes.is_empty() {
+			items.push((1, yard::label("No changes recorded", StrokeColor::CommentOnBackground, Cling::Left)));
+		}
+		let mut batch = None;
+		for change in &state.changes {
+			if batch != Some(change.batch) {
+				batch = Some(change.batch);
+				let text = format!("{} {}", change.at.format("%Y-%m-%d %H:%M"), change.who);
+				items.push((1, yard::empty()));
+				items.push((1, yard::label(text, StrokeColor::CommentOnBackground, Cling::Left)));
+			}
+			items.push((1, yard::label(format!("  {}", change.describe()), StrokeColor::BodyOnBackground, Cling::Left)));
+		}
+		let yard = render::dialog(
+			"History",
+			link.map(|_| Action::Close),
+			ButtonState::disabled(),
+			None,
+			yard::list(YardId::HistoryList.as_i32(), 0, items),
+		);
+		Some(yard)
+	}
+}
@@ -0,0 +1,806 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chad_core::chad::Chad;
+use chad_core::core::Squad;
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::compute::{self, LotLeft, Order, Side};
+use crate::history::{self, Change, Move, Record, UndoStacks};
+use crate::positions::LotChange;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+const ANNEX_FILE: &str = "annex.json";
+
+/// The chad owner of the default profile. Squads made before profiles
+/// existed belong to it.
+const DEFAULT_OWNER: u64 = 5000;
+
+/// Trove's handle on the chad store.
+///
+/// Chad only ever adds records, so anything trove needs to take back,
+/// like a removed member or squad, lives in an annex file beside the
+/// chad data and is applied to squads as they are read.
+///
+/// Edits inside a batch are saved together when the batch ends.
+#[derive(Clone, Debug)]
+pub struct Vault {
+	chad: Chad,
+	profile: Arc<Mutex<String>>,
+	data_dir: PathBuf,
+	annex_path: PathBuf,
+	annex: Arc<Mutex<Annex>>,
+	annex_unsaved: Arc<Mutex<bool>>,
+	history_path: PathBuf,
+	journal: Arc<Mutex<Journal>>,
+	batch: Arc<Mutex<Option<u64>>>,
+	replaying: Arc<Mutex<bool>>,
+}
+
+/// Changes to every squad in the order they were made.
+#[derive(Debug, Default)]
+struct Journal {
+	changes: Vec<Change>,
+	/// How many of the changes the history file holds.
+	saved: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Annex {
+	#[serde(default)]
+	removed_squads: HashSet<u64>,
+	#[serde(default)]
+	removed_members: HashSet<(u64, String)>,
+	#[serde(default)]
+	squads: HashMap<u64, SquadAnnex>,
+	/// Batches each profile can undo and redo.
+	#[serde(default)]
+	undo_stacks: BTreeMap<String, UndoStacks>,
+	/// Chad owner ids by profile name.
+	#[serde(default)]
+	profiles: BTreeMap<String, u64>,
+	/// The profile the picker last switched to.
+	#[serde(default)]
+	profile: Option<String>,
+}
+
+/// What trove keeps about a squad beyond what chad records.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SquadAnnex {
+	/// Unspent cash by account. Empty until the cash is split by account.
+	#[serde(default)]
+	pub cash: BTreeMap<String, f64>,
+	#[serde(default)]
+	pub weighting: Weighting,
+	/// Member symbols from highest rank to lowest. Members missing from it
+	/// follow in chad's order.
+	#[serde(default)]
+	pub order: Vec<String>,
+	#[serde(default)]
+	pub band: Band,
+	/// Bands that replace the squad band for single members.
+	#[serde(default)]
+	pub member_bands: BTreeMap<String, Band>,
+	/// Cost basis of lots by lot id.
+	#[serde(default)]
+	pub lots: BTreeMap<u64, LotBasis>,
+	/// Recorded trades, oldest first.
+	#[serde(default)]
+	pub trades: Vec<Trade>,
+}
+
+impl SquadAnnex {
+	pub fn member_band(&self, symbol: &str) -> Band {
+		self.member_bands.get(symbol).cloned().unwrap_or(self.band)
+	}
+}
+
+/// Cost basis of a lot and when it was acquired.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LotBasis {
+	/// Total cost of the lot's shares.
+	pub cost: Option<f64>,
+	pub acquired: Option<NaiveDate>,
+}
+
+impl LotBasis {
+	pub fn is_set(&self) -> bool {
+		self.cost.is_some() || self.acquired.is_some()
+	}
+}
+
+/// A buy or sell recorded against a squad's lots.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+	pub side: Side,
+	pub symbol: String,
+	pub account: String,
+	pub shares: f64,
+	pub price: f64,
+	pub date: NaiveDate,
+}
+
+impl Trade {
+	pub fn amount(&self) -> f64 { self.shares * self.price }
+
+	/// Shares must be more than zero and the price a number of 0 or more.
+	pub fn check(&self) -> Result<(), &'static str> {
+		if !(self.shares.is_finite() && self.shares > 0.0) {
+			Err("Shares must be more than zero")
+		} else if !(self.price.is_finite() && self.price >= 0.0) {
+			Err("Price must be a number of 0 or more")
+		} else {
+			Ok(())
+		}
+	}
+}
+
+/// How far a member may drift before it needs rebalancing.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Band {
+	/// Percentage points of the squad total.
+	pub absolute: Option<f64>,
+	/// Percent of the member's own target.
+	pub relative: Option<f64>,
+}
+
+impl Band {
+	pub fn is_set(&self) -> bool {
+		self.absolute.is_some() || self.relative.is_some()
+	}
+}
+
+/// How the members of a squad get their target portions.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Weighting {
+	/// The portions chad gives each rank.
+	#[default]
+	Rank,
+	/// Each rank weighs `ratio` times the rank above it.
+	Curve(f64),
+	/// A percent for each member by symbol.
+	Percent(BTreeMap<String, f64>),
+}
+
+impl Annex {
+	fn dress(&self, mut squad: Squad) -> Squad {
+		let squad_id = squad.id;
+		let is_removed = |symbol: &str| self.removed_members.contains(&(squad_id, symbol.to_string()));
+		squad.members.retain(|it| !is_removed(&it.symbol));
+		squad.prices.retain(|symbol, _| !is_removed(symbol));
+		squad.lots.retain(|it| !is_removed(&it.symbol));
+		squad
+	}
+}
+
+impl Vault {
+	/// Opens the store under a profile. Without a profile the vault opens
+	/// the one the picker last switched to. A missing annex starts empty
+	/// but one that cannot be read is an error, so a damaged file is never
+	/// written over.
+	pub fn connect(data_dir: &PathBuf, profile: Option<&str>) -> io::Result<Self> {
+		let chad = Chad::connect(data_dir);
+		let annex_path = data_dir.join(ANNEX_FILE);
+		let annex = match fs::read_to_string(&annex_path) {
+			Ok(json) => serde_json::from_str::<Annex>(&json).map_err(|error| damaged(&annex_path, error))?,
+			Err(error) if error.kind() == io::ErrorKind::NotFound => Annex::default(),
+			Err(error) => return Err(error),
+		};
+		let history_path = data_dir.join(history::HISTORY_FILE);
+		let journal = match fs::read_to_string(&history_path) {
+			Ok(text) => {
+				let changes = history::parse_lines(&text).map_err(|error| damaged(&history_path, error))?;
+				Journal { saved: changes.len(), changes }
+			}
+			Err(error) if error.kind() == io::ErrorKind::NotFound => Journal::default(),
+			Err(error) => return Err(error),
+		};
+		let profile = profile.map(str::to_string)
+			.or_else(|| annex.profile.clone())
+			.unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+		let vault = Vault {
+			chad,
+			profile: Arc::new(Mutex::new(DEFAULT_PROFILE.to_string())),
+			data_dir: data_dir.to_owned(),
+			annex_path,
+			annex: Arc::new(Mutex::new(annex)),
+			annex_unsaved: Arc::new(Mutex::new(false)),
+			history_path,
+			journal: Arc::new(Mutex::new(journal)),
+			batch: Arc::new(Mutex::new(None)),
+			replaying: Arc::new(Mutex::new(false)),
+		};
+		vault.open_profile(&profile)?;
+		Ok(vault)
+	}
+
+	pub fn profile(&self) -> String {
+		self.profile.lock().expect("Profile lock").to_owned()
+	}
+
+	/// Names of all profiles including the default.
+	pub fn profiles(&self) -> Vec<String> {
+		let annex = self.annex.lock().expect("Annex lock");
+		let mut profiles = annex.profiles.keys().cloned().collect::<Vec<_>>();
+		if !annex.profiles.contains_key(DEFAULT_PROFILE) {
+			profiles.insert(0, DEFAULT_PROFILE.to_string());
+		}
+		profiles
+	}
+
+	/// Switches to a profile and remembers it for the next start. Each
+	/// profile keeps its own undo and redo.
+	pub fn switch_profile(&self, name: &str) -> io::Result<()> {
+		self.open_profile(name)?;
+		self.write_annex(|annex| {
+			annex.profile = Some(name.to_string());
+		})
+	}
+
+	/// Adds a profile with a chad owner of its own. Adding a profile that
+	/// exists changes nothing.
+	pub fn add_profile(&self, name: &str) -> io::Result<()> {
+		let is_new = !self.annex.lock().expect("Annex lock").profiles.contains_key(name);
+		if is_new {
+			let owner = if name == DEFAULT_PROFILE { DEFAULT_OWNER } else { rand::random() };
+			self.write_annex(|annex| {
+				annex.profiles.insert(name.to_string(), owner);
+			})?;
+		}
+		Ok(())
+	}
+
+	/// Opens a profile that exists. The default profile always does.
+	fn open_profile(&self, name: &str) -> io::Result<()> {
+		if name == DEFAULT_PROFILE {
+			self.add_profile(name)?;
+		} else if !self.annex.lock().expect("Annex lock").profiles.contains_key(name) {
+			return Err(io::Error::new(io::ErrorKind::NotFound, format!("No profile {}", name)));
+		}
+		*self.profile.lock().expect("Profile lock") = name.to_string();
+		Ok(())
+	}
+
+	fn owner(&self) -> u64 {
+		let profile = self.profile();
+		self.annex.lock().expect("Annex lock").profiles.get(&profile).cloned().expect("Profile has owner")
+	}
+
+	pub fn data_dir(&self) -> &Path {
+		&self.data_dir
+	}
+
+	pub fn squads(&self) -> Vec<Squad> {
+		let owner = self.owner();
+		let annex = self.annex.lock().expect("Annex lock");
+		self.chad.snap().squads(owner).into_iter()
+			.filter(|it| !annex.removed_squads.contains(&it.id))
+			.map(|it| annex.dress(it))
+			.collect()
+	}
+
+	pub fn squad(&self, squad_id: u64) -> Option<Squad> {
+		let squad = self.chad.snap().squads(self.owner()).into_iter().find(|it| it.id == squad_id)?;
+		let annex = self.annex.lock().expect("Annex lock");
+		if annex.removed_squads.contains(&squad_id) {
+			None
+		} else {
+			Some(annex.dress(squad))
+		}
+	}
+
+	pub fn squad_annex(&self, squad_id: u64) -> SquadAnnex {
+		let annex = self.annex.lock().expect("Annex lock");
+		annex.squads.get(&squad_id).cloned().unwrap_or_default()
+	}
+
+	/// Changes to a squad, oldest first.
+	pub fn history(&self, squad_id: u64) -> Vec<Change> {
+		let journal = self.journal.lock().expect("Journal lock");
+		journal.changes.iter().filter(|it| it.squad_id == squad_id).cloned().collect()
+	}
+
+	/// Runs edits so their changes share a batch in the history and are
+	/// saved once at the end.
+	pub fn batch<T>(&self, edits: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+		let is_outer = {
+			let mut batch = self.batch.lock().expect("Batch lock");
+			let is_outer = batch.is_none();
+			if is_outer {
+				*batch = Some(rand::random());
+			}
+			is_outer
+		};
+		let result = edits();
+		if is_outer {
+			*self.batch.lock().expect("Batch lock") = None;
+			// Save even after a failed edit since chad keeps the edits
+			// that came before it.
+			let saved = self.save();
+			let value = result?;
+			saved?;
+			Ok(value)
+		} else {
+			result
+		}
+	}
+
+	pub fn add_squad(&self, squad_id: u64, name: &str) -> io::Result<()> {
+		self.add_owned_squad(squad_id, name, self.owner())
+	}
+
+	fn add_owned_squad(&self, squad_id: u64, name: &str, owner: u64) -> io::Result<()> {
+		let before = self.squad(squad_id).map(|it| Record::Squad { name: it.name, owner: it.owner });
+		self.chad.add_squad(squad_id, name, owner);
+		self.write_annex(|annex| {
+			annex.removed_squads.remove(&squad_id);
+		})?;
+		self.log(squad_id, before, Some(Record::Squad { name: name.to_string(), owner }))
+	}
+
+	/// Chad keys squads by id so adding over an existing squad renames it.
+	pub fn rename_squad(&self, squad_id: u64, name: &str) -> io::Result<()> {
+		self.add_squad(squad_id, name)
+	}
+
+	/// Starts a new squad with the members, prices, targets and bands of an
+	/// existing one. Lots and unspent cash stay with the original.
+	pub fn duplicate_squad(&self, squad: &Squad, squad_id: u64, name: &str) -> io::Result<()> {
+		let annex = self.squad_annex(squad.id);
+		self.batch(|| {
+			self.add_squad(squad_id, name)?;
+			for member in &squad.members {
+				let price = squad.prices.get(&member.symbol).cloned().unwrap_or(0.0);
+				self.add_member(squad_id, &member.symbol, price)?;
+			}
+			self.set_weighting(squad_id, annex.weighting)?;
+			self.set_member_order(squad_id, annex.order)?;
+			self.set_band(squad_id, annex.band)?;
+			for (symbol, band) in annex.member_bands {
+				self.set_member_band(squad_id, &symbol, band)?;
+			}
+			Ok(())
+		})
+	}
+
+	pub fn del_squad(&self, squad_id: u64) -> io::Result<()> {
+		let before = self.squad(squad_id).map(|it| Record::Squad { name: it.name, owner: it.owner });
+		self.write_annex(|annex| {
+			annex.removed_squads.insert(squad_id);
+		})?;
+		self.log(squad_id, before, None)
+	}
+
+	pub fn add_member(&self, squad_id: u64, symbol: &str, price: f64) -> io::Result<()> {
+		let before = self.member_record(squad_id, symbol);
+		self.chad.add_member(squad_id, symbol, price);
+		self.write_annex(|annex| {
+			annex.removed_members.remove(&(squad_id, symbol.to_string()));
+		})?;
+		self.log(squad_id, before, Some(Record::Member { symbol: symbol.to_string(), price }))
+	}
+
+	/// Chad records a price with its member so adding the member again
+	/// replaces the price.
+	pub fn set_price(&self, squad_id: u64, symbol: &str, price: f64) -> io::Result<()> {
+		let before = self.member_record(squad_id, symbol);
+		self.chad.add_member(squad_id, symbol, price);
+		self.log(squad_id, before, Some(Record::Member { symbol: symbol.to_string(), price }))
+	}
+
+	/// Moves a member and its lots to a new symbol.
+	pub fn rename_member(&self, squad: &Squad, symbol: &str, new_symbol: &str, price: f64) -> io::Result<()> {
+		if squad.members.iter().any(|it| it.symbol == new_symbol) {
+			return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is already a member", new_symbol)));
+		}
+		self.batch(|| {
+			self.add_member(squad.id, new_symbol, price)?;
+			for lot in squad.lots.iter().filter(|it| it.symbol == symbol) {
+				self.add_lot(squad.id, lot.id, new_symbol, &lot.account, lot.shares)?;
+			}
+			let annex = self.squad_annex(squad.id);
+			if let Weighting::Percent(mut percents) = annex.weighting {
+				if let Some(percent) = percents.remove(symbol) {
+					percents.insert(new_symbol.to_string(), percent);
+					self.set_weighting(squad.id, Weighting::Percent(percents))?;
+				}
+			}
+			if annex.order.iter().any(|it| it == symbol) {
+				let order = annex.order.iter().map(|it| if it == symbol { new_symbol.to_string() } else { it.to_owned() }).collect();
+				self.set_member_order(squad.id, order)?;
+			}
+			if let Some(band) = annex.member_bands.get(symbol) {
+				self.set_member_band(squad.id, new_symbol, *band)?;
+				self.set_member_band(squad.id, symbol, Band::default())?;
+			}
+			self.remove_member(squad.id, symbol)
+		})
+	}
+
+	/// Removes a member from the squad along with all of its lots.
+	pub fn del_member(&self, squad: &Squad, symbol: &str) -> io::Result<()> {
+		self.batch(|| {
+			for lot in squad.lots.iter().filter(|it| it.symbol == symbol) {
+				self.del_lot(squad.id, lot.id)?;
+			}
+			let annex = self.squad_annex(squad.id);
+			if let Weighting::Percent(mut percents) = annex.weighting {
+				if percents.remove(symbol).is_some() {
+					self.set_weighting(squad.id, Weighting::Percent(percents))?;
+				}
+			}
+			if annex.order.iter().any(|it| it == symbol) {
+				let order = annex.order.iter().filter(|it| *it != symbol).cloned().collect();
+				self.set_member_order(squad.id, order)?;
+			}
+			self.set_member_band(squad.id, symbol, Band::default())?;
+			self.remove_member(squad.id, symbol)
+		})
+	}
+
+	fn remove_member(&self, squad_id: u64, symbol: &str) -> io::Result<()> {
+		let before = self.member_record(squad_id, symbol);
+		self.write_annex(|annex| {
+			annex.removed_members.insert((squad_id, symbol.to_string()));
+		})?;
+		self.log(squad_id, before, None)
+	}
+
+	pub fn add_lot(&self, squad_id: u64, lot_id: u64, symbol: &str, account: &str, shares: f64) -> io::Result<()> {
+		let before = self.lot_record(squad_id, lot_id);
+		self.chad.add_lot(squad_id, lot_id, symbol, account, shares);
+		let basis = self.squad_annex(squad_id).lots.get(&lot_id).cloned().unwrap_or_default();
+		let after = Record::Lot { lot_id, symbol: symbol.to_string(), account: account.to_string(), shares, basis };
+		self.log(squad_id, before, Some(after))
+	}
+
+	pub fn del_lot(&self, squad_id: u64, lot_id: u64) -> io::Result<()> {
+		let before = self.lot_record(squad_id, lot_id);
+		self.chad.del_lot(squad_id, lot_id);
+		self.write_annex(|annex| {
+			if let Some(squad_annex) = annex.squads.get_mut(&squad_id) {
+				squad_annex.lots.remove(&lot_id);
+			}
+		})?;
+		self.log(squad_id, before, None)
+	}
+
+	pub fn set_lot_basis(&self, squad_id: u64, lot_id: u64, basis: LotBasis) -> io::Result<()> {
+		let before = self.lot_record(squad_id, lot_id);
+		self.write_annex(|annex| {
+			let lots = &mut annex.squads.entry(squad_id).or_default().lots;
+			if basis.is_set() {
+				lots.insert(lot_id, basis);
+			} else {
+				lots.remove(&lot_id);
+			}
+		})?;
+		match before.as_ref().and_then(|it| it.with_basis(basis)) {
+			None => Ok(()),
+			after => self.log(squad_id, before, after),
+		}
+	}
+
+	/// Applies the lot changes of a positions import as one batch.
+	pub fn import_lots(&self, squad_id: u64, changes: &[LotChange]) -> io::Result<()> {
+		self.batch(|| {
+			for change in changes {
+				match change {
+					LotChange::Add { symbol, account, shares, cost } => {
+						let lot_id = rand::random();
+						self.add_lot(squad_id, lot_id, symbol, account, *shares)?;
+						self.set_lot_basis(squad_id, lot_id, LotBasis { cost: *cost, acquired: None })?;
+					}
+					LotChange::Update { lot_id, symbol, account, shares, basis, .. } => {
+						self.add_lot(squad_id, *lot_id, symbol, account, *shares)?;
+						if let Some(basis) = basis {
+							self.set_lot_basis(squad_id, *lot_id, *basis)?;
+						}
+					}
+					LotChange::Remove { lot_id, .. } => self.del_lot(squad_id, *lot_id)?,
+				}
+			}
+			Ok(())
+		})
+	}
+
+	pub fn set_weighting(&self, squad_id: u64, weighting: Weighting) -> io::Result<()> {
+		let before = Record::Weighting(self.squad_annex(squad_id).weighting);
+		self.write_annex(|annex| {
+			annex.squads.entry(squad_id).or_default().weighting = weighting.clone();
+		})?;
+		self.log(squad_id, Some(before), Some(Record::Weighting(weighting)))
+	}
+
+	pub fn set_member_order(&self, squad_id: u64, order: Vec<String>) -> io::Result<()> {
+		let before = Record::Order(self.squad_annex(squad_id).order);
+		self.write_annex(|annex| {
+			annex.squads.entry(squad_id).or_default().order = order.clone();
+		})?;
+		self.log(squad_id, Some(before), Some(Record::Order(order)))
+	}
+
+	pub fn set_band(&self, squad_id: u64, band: Band) -> io::Result<()> {
+		let before = Record::Band { symbol: None, band: self.squad_annex(squad_id).band };
+		self.write_annex(|annex| {
+			annex.squads.entry(squad_id).or_default().band = band;
+		})?;
+		self.log(squad_id, Some(before), Some(Record::Band { symbol: None, band }))
+	}
+
+	/// Sets the band of a single member. An unset band falls back to the
+	/// squad band.
+	pub fn set_member_band(&self, squad_id: u64, symbol: &str, band: Band) -> io::Result<()> {
+		let old_band = self.squad_annex(squad_id).member_bands.get(symbol).cloned().unwrap_or_default();
+		self.write_annex(|annex| {
+			let member_bands = &mut annex.squads.entry(squad_id).or_default().member_bands;
+			if band.is_set() {
+				member_bands.insert(symbol.to_string(), band);
+			} else {
+				member_bands.remove(symbol);
+			}
+		})?;
+		let symbol = Some(symbol.to_string());
+		self.log(squad_id, Some(Record::Band { symbol: symbol.clone(), band: old_band }), Some(Record::Band { symbol, band }))
+	}
+
+	/// Applies a trade to the squad's lots and unspent cash and logs it.
+	/// A buy adds a lot with the trade as its cost basis. A sell takes
+	/// shares from the account's oldest lots first.
+	pub fn record_trade(&self, squad: &Squad, trade: Trade) -> Result<(), String> {
+		trade.check()?;
+		let annex = self.squad_annex(squad.id);
+		let lefts = match trade.side {
+			Side::Buy => Vec::new(),
+			Side::Sell => {
+				let order = Order { side: Side::Sell, symbol: trade.symbol.to_owned(), account: trade.account.to_owned(), shares: trade.shares, price: trade.price };
+				compute::sell_lots(&order, &squad.lots, &annex, trade.date)?
+			}
+		};
+		self.batch(|| self.apply_trade(squad, &annex, trade, &lefts)).map_err(|error| format!("Could not save: {}", error))
+	}
+
+	fn apply_trade(&self, squad: &Squad, annex: &SquadAnnex, trade: Trade, lefts: &[LotLeft]) -> io::Result<()> {
+		if trade.side == Side::Buy {
+			let lot_id = rand::random();
+			self.add_lot(squad.id, lot_id, &trade.symbol, &trade.account, trade.shares)?;
+			self.set_lot_basis(squad.id, lot_id, LotBasis { cost: Some(trade.amount()), acquired: Some(trade.date) })?;
+		}
+		for left in lefts {
+			match left {
+				LotLeft::Kept { lot_id, shares, basis } => {
+					self.add_lot(squad.id, *lot_id, &trade.symbol, &trade.account, *shares)?;
+					if let Some(basis) = basis {
+						self.set_lot_basis(squad.id, *lot_id, *basis)?;
+					}
+				}
+				LotLeft::Emptied { lot_id } => self.del_lot(squad.id, *lot_id)?,
+			}
+		}
+		let amount = match trade.side {
+			Side::Buy => -trade.amount(),
+			Side::Sell => trade.amount(),
+		};
+		if annex.cash.is_empty() {
+			self.set_unspent(squad.id, squad.unspent + amount)?;
+		} else {
+			let mut cash = annex.cash.clone();
+			*cash.entry(trade.account.to_owned()).or_insert(0.0) += amount;
+			self.set_account_cash(squad.id, cash)?;
+		}
+		self.add_trade(squad.id, trade)
+	}
+
+	fn add_trade(&self, squad_id: u64, trade: Trade) -> io::Result<()> {
+		self.write_annex(|annex| {
+			annex.squads.entry(squad_id).or_default().trades.push(trade.clone());
+		})?;
+		self.log(squad_id, None, Some(Record::Trade(trade)))
+	}
+
+	fn del_trade(&self, squad_id: u64, trade: &Trade) -> io::Result<()> {
+		self.write_annex(|annex| {
+			let trades = &mut annex.squads.entry(squad_id).or_default().trades;
+			if let Some(index) = trades.iter().rposition(|it| it == trade) {
+				trades.remove(index);
+			}
+		})?;
+		self.log(squad_id, Some(Record::Trade(trade.to_owned())), None)
+	}
+
+	pub fn can_undo(&self) -> bool {
+		!self.stacks().undo.is_empty()
+	}
+
+	pub fn can_redo(&self) -> bool {
+		!self.stacks().redo.is_empty()
+	}
+
+	/// Reverts the most recent batch of changes and returns the number of
+	/// changes reverted.
+	pub fn undo(&self) -> io::Result<usize> {
+		let batch = match self.stacks().undo.last() {
+			None => return Ok(0),
+			Some(batch) => *batch,
+		};
+		let moves = history::undo_moves(&self.journal.lock().expect("Journal lock").changes, batch);
+		self.batch(|| {
+			self.replay(&moves)?;
+			self.edit_stacks(UndoStacks::undone)
+		})?;
+		Ok(moves.len())
+	}
+
+	/// Applies again the most recently undone batch and returns the number
+	/// of changes applied.
+	pub fn redo(&self) -> io::Result<usize> {
+		let batch = match self.stacks().redo.last() {
+			None => return Ok(0),
+			Some(batch) => *batch,
+		};
+		let moves = history::redo_moves(&self.journal.lock().expect("Journal lock").changes, batch);
+		self.batch(|| {
+			self.replay(&moves)?;
+			self.edit_stacks(UndoStacks::redone)
+		})?;
+		Ok(moves.len())
+	}
+
+	/// Moves each value from one record to the other. The replay is logged
+	/// as a batch of its own but leaves the undo and redo stacks alone.
+	fn replay(&self, moves: &[Move]) -> io::Result<()> {
+		*self.replaying.lock().expect("Replaying lock") = true;
+		let result = self.batch(|| {
+			for (squad_id, from, to) in moves {
+				match (from, to) {
+					(_, Some(to)) => self.restore(*squad_id, to)?,
+					(Some(from), None) => self.remove(*squad_id, from)?,
+					(None, None) => {}
+				}
+			}
+			Ok(())
+		});
+		*self.replaying.lock().expect("Replaying lock") = false;
+		result
+	}
+
+	fn restore(&self, squad_id: u64, record: &Record) -> io::Result<()> {
+		match record.to_owned() {
+			// A squad goes back to the owner it had when the change was made.
+			Record::Squad { name, owner } => self.add_owned_squad(squad_id, &name, owner),
+			Record::Member { symbol, price } => self.add_member(squad_id, &symbol, price),
+			Record::Lot { lot_id, symbol, account, shares, basis } => {
+				self.add_lot(squad_id, lot_id, &symbol, &account, shares)?;
+				self.set_lot_basis(squad_id, lot_id, basis)
+			}
+			Record::Unspent { amount, accounts } => if accounts.is_empty() {
+				self.set_unspent(squad_id, amount)
+			} else {
+				self.set_account_cash(squad_id, accounts)
+			},
+			Record::Weighting(weighting) => self.set_weighting(squad_id, weighting),
+			Record::Order(order) => self.set_member_order(squad_id, order),
+			Record::Band { symbol: None, band } => self.set_band(squad_id, band),
+			Record::Band { symbol: Some(symbol), band } => self.set_member_band(squad_id, &symbol, band),
+			Record::Trade(trade) => self.add_trade(squad_id, trade),
+		}
+	}
+
+	fn remove(&self, squad_id: u64, record: &Record) -> io::Result<()> {
+		match record {
+			Record::Squad { .. } => self.del_squad(squad_id),
+			Record::Member { symbol, .. } => self.remove_member(squad_id, symbol),
+			Record::Lot { lot_id, .. } => self.del_lot(squad_id, *lot_id),
+			Record::Trade(trade) => self.del_trade(squad_id, trade),
+			Record::Unspent { .. } | Record::Weighting(_) | Record::Order(_) | Record::Band { .. } => Ok(()),
+		}
+	}
+
+	/// Sets a single unspent amount and drops any split by account.
+	pub fn set_unspent(&self, squad_id: u64, unspent: f64) -> io::Result<()> {
+		let before = self.unspent_record(squad_id);
+		self.chad.set_unspent(squad_id, unspent);
+		self.write_annex(|annex| {
+			if let Some(squad) = annex.squads.get_mut(&squad_id) {
+				squad.cash.clear();
+			}
+		})?;
+		self.log(squad_id, before, Some(Record::Unspent { amount: unspent, accounts: BTreeMap::new() }))
+	}
+
+	/// Sets unspent cash by account. Chad keeps the total.
+	pub fn set_account_cash(&self, squad_id: u64, cash: BTreeMap<String, f64>) -> io::Result<()> {
+		let before = self.unspent_record(squad_id);
+		let amount = cash.values().sum();
+		self.chad.set_unspent(squad_id, amount);
+		self.write_annex(|annex| {
+			annex.squads.entry(squad_id).or_default().cash = cash.clone();
+		})?;
+		self.log(squad_id, before, Some(Record::Unspent { amount, accounts: cash }))
+	}
+
+	fn member_record(&self, squad_id: u64, symbol: &str) -> Option<Record> {
+		history::member_record(&self.squad(squad_id)?, symbol)
+	}
+
+	fn lot_record(&self, squad_id: u64, lot_id: u64) -> Option<Record> {
+		history::lot_record(&self.squad(squad_id)?.lots, &self.squad_annex(squad_id), lot_id)
+	}
+
+	fn unspent_record(&self, squad_id: u64) -> Option<Record> {
+		Some(history::unspent_record(self.squad(squad_id)?.unspent, &self.squad_annex(squad_id)))
+	}
+
+	/// Adds a change to the squad's history unless nothing changed.
+	fn log(&self, squad_id: u64, before: Option<Record>, after: Option<Record>) -> io::Result<()> {
+		if before == after {
+			return Ok(());
+		}
+		let batch = self.batch.lock().expect("Batch lock").unwrap_or_else(rand::random);
+		let replaying = *self.replaying.lock().expect("Replaying lock");
+		let change = Change { squad_id, batch, at: Local::now().naive_local(), who: history::who(), before, after };
+		self.journal.lock().expect("Journal lock").changes.push(change);
+		let is_new_batch = !replaying && self.stacks().undo.last() != Some(&batch);
+		if is_new_batch {
+			self.edit_stacks(|stacks| stacks.push(batch))
+		} else {
+			self.save_unless_batched()
+		}
+	}
+
+	/// The undo and redo of the current profile.
+	fn stacks(&self) -> UndoStacks {
+		let profile = self.profile();
+		self.annex.lock().expect("Annex lock").undo_stacks.get(&profile).cloned().unwrap_or_default()
+	}
+
+	fn edit_stacks(&self, edit: impl FnOnce(&mut UndoStacks)) -> io::Result<()> {
+		let profile = self.profile();
+		self.write_annex(|annex| edit(annex.undo_stacks.entry(profile).or_default()))
+	}
+
+	/// Edits the annex and saves it unless a batch will.
+	fn write_annex(&self, edit: impl FnOnce(&mut Annex)) -> io::Result<()> {
+		edit(&mut self.annex.lock().expect("Annex lock"));
+		*self.annex_unsaved.lock().expect("Annex unsaved lock") = true;
+		self.save_unless_batched()
+	}
+
+	fn save_unless_batched(&self) -> io::Result<()> {
+		if self.batch.lock().expect("Batch lock").is_some() {
+			Ok(())
+		} else {
+			self.save()
+		}
+	}
+
+	/// Appends new changes to the history file and writes the annex when
+	/// it changed. The annex is written beside itself and renamed over the
+	/// old one so a failed write leaves the old annex whole.
+	fn save(&self) -> io::Result<()> {
+		let mut journal = self.journal.lock().expect("Journal lock");
+		if journal.saved < journal.changes.len() {
+			let lines = history::to_lines(&journal.changes[journal.saved..]);
+			OpenOptions::new().create(true).append(true).open(&self.history_path)?.write_all(lines.as_bytes())?;
+			journal.saved = journal.changes.len();
+		}
+		let mut annex_unsaved = self.annex_unsaved.lock().expect("Annex unsaved lock");
+		if *annex_unsaved {
+			let json = serde_json::to_string_pretty(&*self.annex.lock().expect("Annex lock")).expect("Annex to json");
+			let temp_path = self.annex_path.with_extension("json.tmp");
+			fs::write(&temp_path, json)?;
+			fs::rename(&temp_path, &self.annex_path)?;
+			*annex_unsaved = false;
+		}
+		Ok(())
+	}
+}
+
+fn damaged(path: &Path, error: impl std::fmt::Display) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, format!("{} is damaged: {}", path.display(), error))
+}